@@ -1,20 +1,36 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::Result;
 use bytes::Bytes;
+use clap::ValueEnum;
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+        RETRY_AFTER,
+    },
+    Client, StatusCode,
+};
 use serde::Deserialize;
 
-type GameKeyResponse = String;
+use crate::{
+    error::{Error, Result},
+    game::{Channel, Game, Platform, Target},
+    history::History,
+};
 
-#[derive(Deserialize)]
-struct CytrusResponse {
+pub type GameKeyResponse = Game;
+
+#[derive(Deserialize, Debug)]
+pub struct CytrusResponse {
     pub name: String,
     pub version: u8,
     pub games: HashMap<GameKeyResponse, GameDataResponse>,
 }
 
-#[derive(Deserialize)]
-struct GameDataResponse {
+#[derive(Deserialize, Debug)]
+pub struct GameDataResponse {
     pub assets: Option<AssetsResponse>,
     #[serde(alias = "gameId")]
     pub game_id: u8,
@@ -23,64 +39,655 @@ struct GameDataResponse {
     pub platforms: PlatformResponse,
 }
 
-#[derive(Deserialize)]
-struct AssetsResponse {
-    meta: Option<VersionResponse>,
+#[derive(Deserialize, Debug)]
+pub struct AssetsResponse {
+    pub meta: Option<VersionResponse>,
 }
 
-#[derive(Deserialize)]
-struct PlatformResponse {
-    darwin: Option<VersionResponse>,
-    linux: Option<VersionResponse>,
-    windows: Option<VersionResponse>,
+#[derive(Deserialize, Debug)]
+pub struct PlatformResponse {
+    pub darwin: Option<VersionResponse>,
+    pub linux: Option<VersionResponse>,
+    pub windows: Option<VersionResponse>,
 }
 
 #[derive(Deserialize, Debug)]
-struct VersionResponse {
+pub struct VersionResponse {
     pub beta: Option<String>,
     pub main: Option<String>,
+    /// channels other than main and beta
+    #[serde(flatten)]
+    pub others: HashMap<String, Option<String>>,
+}
+
+impl CytrusResponse {
+    /// Version of a channel of a game, the error tells what cytrus.json has
+    /// instead
+    pub fn version(&self, game: &Game, target: Target, channel: &Channel) -> Result<String> {
+        let data = self.games.get(game).ok_or_else(|| {
+            let mut games: Vec<Game> = self.games.keys().cloned().collect();
+            games.sort();
+
+            Error::UnknownGame {
+                game: game.clone(),
+                games,
+            }
+        })?;
+
+        let versions = match target {
+            Target::Platform(platform) => {
+                data.platforms
+                    .get(platform)
+                    .ok_or_else(|| Error::MissingPlatform {
+                        game: game.clone(),
+                        platform,
+                        platforms: data.platforms.available(),
+                    })?
+            }
+            Target::Assets => data
+                .assets
+                .as_ref()
+                .and_then(|assets| assets.meta.as_ref())
+                .ok_or_else(|| Error::MissingAssets { game: game.clone() })?,
+        };
+
+        versions
+            .get(channel)
+            .cloned()
+            .ok_or_else(|| Error::MissingChannel {
+                game: game.clone(),
+                target,
+                channel: channel.clone(),
+                channels: versions.available(),
+            })
+    }
+}
+
+impl PlatformResponse {
+    pub fn get(&self, platform: Platform) -> Option<&VersionResponse> {
+        match platform {
+            Platform::Darwin => self.darwin.as_ref(),
+            Platform::Linux => self.linux.as_ref(),
+            Platform::Windows => self.windows.as_ref(),
+        }
+    }
+
+    /// Platforms the game is released on
+    pub fn available(&self) -> Vec<Platform> {
+        Platform::value_variants()
+            .iter()
+            .copied()
+            .filter(|platform| self.get(*platform).is_some())
+            .collect()
+    }
+}
+
+impl VersionResponse {
+    pub fn get(&self, channel: &Channel) -> Option<&String> {
+        match channel {
+            Channel::Main => self.main.as_ref(),
+            Channel::Beta => self.beta.as_ref(),
+            Channel::Other(name) => self.others.get(name)?.as_ref(),
+        }
+    }
+
+    /// Channels with a version
+    pub fn available(&self) -> Vec<Channel> {
+        let mut channels = vec![Channel::Main, Channel::Beta];
+        channels.extend(self.others.keys().map(|name| Channel::Other(name.clone())));
+
+        channels.retain(|channel| self.get(channel).is_some());
+        channels.sort();
+        channels
+    }
+}
+
+pub const CDN_URL: &str = "https://cytrus.cdn.ankama.com";
+
+/// `ETag` and `Last-Modified` of the last cytrus.json, sent back so the cdn
+/// answers `304 Not Modified` when it didn't change
+#[derive(Default, Clone)]
+pub struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+/// How failed requests are retried, the delay before a retry is drawn
+/// between zero and `base_delay * 2^attempt`, capped at `max_delay`
+///
+/// Only errors that may go away are retried: timeouts, connection errors,
+/// `429 Too Many Requests` and server errors. A `429` waits as long as its
+/// `Retry-After` header asks, and isn't retried when that is longer than
+/// `max_delay`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// retries after the first attempt, 0 to never retry
+    pub retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, with full jitter so clients that
+    /// failed together don't retry together
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        ceiling.mul_f64(fastrand::f64())
+    }
 }
 
-pub struct Api {}
+/// Connect timeout of a request
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time without receiving any data after which a request fails
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn client(connect_timeout: Duration, read_timeout: Duration) -> Client {
+    Client::builder()
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout)
+        .build()
+        .expect("the tls backend of reqwest failed to initialize")
+}
+
+/// A response whose body was read
+struct Fetched {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// Delay asked by a `Retry-After` header, given in seconds or as a date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// The error may go away when the request is sent again
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request() || err.is_body()
+}
+
+/// Client of the cytrus cdn, the connections are pooled and shared by every
+/// request made through it
+#[derive(Clone)]
+pub struct Api {
+    client: Client,
+    url: String,
+    history: Option<History>,
+    retry: RetryPolicy,
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Api::new()
+    }
+}
 
 impl Api {
-    pub async fn get_latest_version(game: &String, platform: &str, beta: &bool) -> Result<String> {
-        let res = reqwest::get("https://cytrus.cdn.ankama.com/cytrus.json").await?;
+    pub fn new() -> Self {
+        Api::with_url(CDN_URL)
+    }
+
+    /// Client of a mirror of the cdn, `url` is the address `cytrus.json` is
+    /// served from
+    pub fn with_url(url: &str) -> Self {
+        Api {
+            client: client(CONNECT_TIMEOUT, READ_TIMEOUT),
+            url: url.trim_end_matches('/').to_string(),
+            history: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Archives every cytrus.json and manifest fetched in `history`, a
+    /// manifest removed from the cdn is read from the archive
+    ///
+    /// The archive is best effort, a history that can't be written is
+    /// reported on stderr and the request goes on.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-        let response = res.json::<CytrusResponse>().await?;
+    /// Replaces the default [`CONNECT_TIMEOUT`] and [`READ_TIMEOUT`]
+    pub fn with_timeouts(mut self, connect_timeout: Duration, read_timeout: Duration) -> Self {
+        self.client = client(connect_timeout, read_timeout);
+        self
+    }
 
-        let platforms = &response.games[game].platforms;
+    /// Runs `archive` on the history, its failure is only reported
+    fn archive(&self, archive: impl FnOnce(&History) -> Result<()>) {
+        if let Some(history) = &self.history {
+            if let Err(err) = archive(history) {
+                eprintln!("Warning: the history was not updated: {err}");
+            }
+        }
+    }
 
-        let platform = match platform {
-            "darwin" => platforms.darwin.as_ref().unwrap(),
-            "linux" => platforms.linux.as_ref().unwrap(),
-            _ => platforms.windows.as_ref().unwrap(),
+    async fn send(&self, url: &str, headers: &HeaderMap) -> Result<Fetched, reqwest::Error> {
+        let res = self.client.get(url).headers(headers.clone()).send().await?;
+
+        Ok(Fetched {
+            status: res.status(),
+            headers: res.headers().clone(),
+            body: res.bytes().await?,
+        })
+    }
+
+    /// Sends a GET request and reads its body, retried as told by the retry
+    /// policy. A response that isn't a success or a `304 Not Modified` is
+    /// an error.
+    async fn request(&self, url: String, headers: HeaderMap) -> Result<Fetched> {
+        let mut attempt = 0;
+
+        let fetched = loop {
+            let result = self.send(&url, &headers).await;
+
+            let (retry, asked) = match &result {
+                Ok(fetched) if fetched.status == StatusCode::TOO_MANY_REQUESTS => {
+                    (true, retry_after(&fetched.headers))
+                }
+                Ok(fetched) => (fetched.status.is_server_error(), None),
+                Err(err) => (is_transient(err), None),
+            };
+
+            let too_long = asked.is_some_and(|delay| delay > self.retry.max_delay);
+            if !retry || too_long || attempt >= self.retry.retries {
+                break result?;
+            }
+
+            tokio::time::sleep(asked.unwrap_or_else(|| self.retry.delay(attempt))).await;
+            attempt += 1;
         };
 
-        if *beta {
-            Ok(platform.beta.clone().unwrap())
-        } else {
-            Ok(platform.main.clone().unwrap())
+        if !fetched.status.is_success() && fetched.status != StatusCode::NOT_MODIFIED {
+            return Err(Error::HttpStatus {
+                url,
+                status: fetched.status,
+            });
+        }
+
+        Ok(fetched)
+    }
+
+    async fn get(&self, url: String) -> Result<Bytes> {
+        Ok(self.request(url, HeaderMap::new()).await?.body)
+    }
+
+    pub async fn get_cytrus(&self) -> Result<CytrusResponse> {
+        let body = self.get(format!("{}/cytrus.json", self.url)).await?;
+
+        self.read_cytrus(&body)
+    }
+
+    /// Fetches cytrus.json unless it didn't change since `validators` were
+    /// filled by the previous call, cheap enough to poll
+    pub async fn get_cytrus_if_changed(
+        &self,
+        validators: &mut Validators,
+    ) -> Result<Option<CytrusResponse>> {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = &validators.etag {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+
+        let fetched = self
+            .request(format!("{}/cytrus.json", self.url), headers)
+            .await?;
+
+        if fetched.status == StatusCode::NOT_MODIFIED {
+            return Ok(None);
         }
+
+        validators.etag = fetched.headers.get(ETAG).cloned();
+        validators.last_modified = fetched.headers.get(LAST_MODIFIED).cloned();
+
+        Ok(Some(self.read_cytrus(&fetched.body)?))
     }
 
+    fn read_cytrus(&self, body: &[u8]) -> Result<CytrusResponse> {
+        let response = serde_json::from_slice::<CytrusResponse>(body)?;
+
+        self.archive(|history| history.record(&response, body));
+
+        Ok(response)
+    }
+
+    pub async fn get_latest_version(
+        &self,
+        game: &Game,
+        target: Target,
+        channel: &Channel,
+    ) -> Result<String> {
+        self.get_cytrus().await?.version(game, target, channel)
+    }
+
+    /// Fetches the manifest of a version, the manifest of a platform lives
+    /// at `<game>/releases/<channel>/<platform>/<version>.manifest` and the
+    /// assets meta at `<game>/assets/<channel>/meta/<version>.manifest`,
+    /// both reference the bundles of the game
     pub async fn get_manifiest(
-        game: &String,
-        platform: &String,
-        version: &String,
-        beta: &bool,
+        &self,
+        game: &Game,
+        target: Target,
+        version: &str,
+        channel: &Channel,
+    ) -> Result<Bytes> {
+        let url = match target {
+            Target::Platform(platform) => format!(
+                "{}/{game}/releases/{channel}/{platform}/{version}.manifest",
+                self.url
+            ),
+            Target::Assets => format!(
+                "{}/{game}/assets/{channel}/meta/{version}.manifest",
+                self.url
+            ),
+        };
+
+        let data = match self.get(url).await {
+            Err(Error::HttpStatus { status, .. }) if status == StatusCode::NOT_FOUND => {
+                let archived = match &self.history {
+                    Some(history) => history.manifest(game, target, channel, version)?,
+                    None => None,
+                };
+
+                return archived
+                    .map(Bytes::from)
+                    .ok_or_else(|| Error::MissingManifest {
+                        game: game.clone(),
+                        target,
+                        channel: channel.clone(),
+                        version: version.to_string(),
+                    });
+            }
+            data => data?,
+        };
+
+        self.archive(|history| history.archive_manifest(game, target, channel, version, &data));
+
+        Ok(data)
+    }
+
+    fn bundle_url(&self, game: &Game, hash: &str) -> String {
+        let prefix = hash.get(0..2).unwrap_or(hash);
+        format!("{}/{game}/bundles/{prefix}/{hash}", self.url)
+    }
+
+    pub async fn get_bundle(&self, game: &Game, hash: &str) -> Result<Bytes> {
+        self.get(self.bundle_url(game, hash)).await
+    }
+
+    /// Fetches `size` bytes at `offset` of a bundle with a range request, the
+    /// range is cut from the whole bundle when the server ignores it
+    pub async fn get_bundle_range(
+        &self,
+        game: &Game,
+        hash: &str,
+        offset: u64,
+        size: u64,
     ) -> Result<Bytes> {
-        let beta = if *beta {
-            String::from("beta")
+        if size == 0 {
+            return Ok(Bytes::new());
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            RANGE,
+            HeaderValue::from_str(&format!("bytes={offset}-{}", offset + size - 1))
+                .expect("a byte range is a valid header value"),
+        );
+
+        let fetched = self.request(self.bundle_url(game, hash), headers).await?;
+        let range = if fetched.status == StatusCode::PARTIAL_CONTENT {
+            0..size as usize
         } else {
-            String::from("main")
+            offset as usize..(offset + size) as usize
         };
 
-        let res = reqwest::get(format!(
-            "https://cytrus.cdn.ankama.com/{game}/releases/{beta}/{platform}/{version}.manifest"
-        ))
-        .await?;
+        if fetched.body.len() < range.end {
+            return Err(Error::TruncatedBundle {
+                bundle: hash.to_string(),
+            });
+        }
+
+        Ok(fetched.body.slice(range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        read_manifest,
+        testing::{bundle_path, manifest, Server, TestFile},
+    };
+
+    const CYTRUS: &str = r#"{"name":"production","version":6,"games":{"dofus":{"name":"Dofus","order":0,"gameId":1,"assets":null,"platforms":{"windows":null,"darwin":null,"linux":{"main":"2.0","beta":null}}}}}"#;
+
+    /// dofus on linux and windows without assets, wakfu with only assets
+    const GAMES: &str = r#"{"name":"production","version":6,"games":{
+        "dofus":{"name":"Dofus","order":0,"gameId":1,"assets":null,"platforms":{"windows":{"main":"2.0","beta":"2.1"},"darwin":null,"linux":{"main":"2.0","beta":null,"ptr":"2.2"}}},
+        "wakfu":{"name":"Wakfu","order":1,"gameId":3,"assets":{"meta":{"main":"1.0","beta":null}},"platforms":{"windows":null,"darwin":null,"linux":null}}
+    }}"#;
+
+    fn games() -> CytrusResponse {
+        serde_json::from_str(GAMES).unwrap()
+    }
+
+    #[test]
+    fn versions_are_read_from_cytrus_json() {
+        let linux = Target::Platform(Platform::Linux);
+
+        assert_eq!(
+            games()
+                .version(&Game::Dofus, linux, &Channel::Main)
+                .unwrap(),
+            "2.0"
+        );
+        assert_eq!(
+            games()
+                .version(&Game::Dofus, linux, &Channel::Other("ptr".to_string()))
+                .unwrap(),
+            "2.2"
+        );
+        assert_eq!(
+            games()
+                .version(&Game::Wakfu, Target::Assets, &Channel::Main)
+                .unwrap(),
+            "1.0"
+        );
+    }
+
+    #[test]
+    fn unknown_games_list_the_known_ones() {
+        let err = games()
+            .version(&Game::Retro, Target::Assets, &Channel::Main)
+            .unwrap_err();
+
+        assert!(
+            matches!(&err, Error::UnknownGame { games, .. } if games == &[Game::Dofus, Game::Wakfu])
+        );
+        assert_eq!(
+            err.to_string(),
+            "unknown game retro, cytrus has dofus, wakfu"
+        );
+    }
+
+    #[test]
+    fn missing_platforms_list_the_released_ones() {
+        let err = games()
+            .version(
+                &Game::Dofus,
+                Target::Platform(Platform::Darwin),
+                &Channel::Main,
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            &err,
+            Error::MissingPlatform { platforms, .. }
+                if platforms == &[Platform::Windows, Platform::Linux]
+        ));
+        assert_eq!(
+            err.to_string(),
+            "dofus has no darwin build, it has windows, linux"
+        );
+
+        let err = games()
+            .version(
+                &Game::Wakfu,
+                Target::Platform(Platform::Linux),
+                &Channel::Main,
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "wakfu has no linux build, it has none");
+    }
+
+    #[test]
+    fn missing_assets_are_errors() {
+        let err = games()
+            .version(&Game::Dofus, Target::Assets, &Channel::Main)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::MissingAssets { game } if game == Game::Dofus));
+    }
+
+    #[test]
+    fn missing_channels_list_the_ones_with_a_version() {
+        let err = games()
+            .version(
+                &Game::Dofus,
+                Target::Platform(Platform::Linux),
+                &Channel::Beta,
+            )
+            .unwrap_err();
+
+        assert!(matches!(
+            &err,
+            Error::MissingChannel { channels, .. }
+                if channels == &[Channel::Main, Channel::Other("ptr".to_string())]
+        ));
+        assert_eq!(
+            err.to_string(),
+            "dofus has no beta version for linux, it has main, ptr"
+        );
+    }
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_fixture_server_stands_in_for_the_cdn() {
+        let test = manifest(vec![("main", vec![TestFile::new("a.txt", b"hello")])]);
+        let (bundle, data) = &test.bundles[0];
+
+        let server = Server::start().await;
+        server.route("/cytrus.json", CYTRUS.as_bytes());
+        server.route_manifest("2.0", &test);
+        server.fail(1);
+
+        let api = Api::with_url(&server.url).with_retry(retry());
+        let game = Game::Dofus;
+        let target = Target::Platform(Platform::Linux);
+
+        // the first request fails and is retried
+        let version = api
+            .get_latest_version(&game, target, &Channel::Main)
+            .await
+            .unwrap();
+        assert_eq!(version, "2.0");
+        assert_eq!(server.requests().len(), 2);
+
+        let manifest = api
+            .get_manifiest(&game, target, &version, &Channel::Main)
+            .await
+            .unwrap();
+        assert!(read_manifest(&manifest).is_ok());
+
+        let fetched = api.get_bundle(&game, bundle).await.unwrap();
+        assert_eq!(fetched.as_ref(), data.as_slice());
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 1);
+
+        assert!(matches!(
+            api.get_manifiest(&game, target, "1.0", &Channel::Main)
+                .await,
+            Err(Error::MissingManifest { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn bundle_ranges_are_cut_from_partial_or_whole_answers() {
+        let server = Server::start().await;
+        server.route(&bundle_path("ab00"), b"0123456789");
+        let api = Api::with_url(&server.url).with_retry(retry());
+
+        let range = api
+            .get_bundle_range(&Game::Dofus, "ab00", 2, 3)
+            .await
+            .unwrap();
+        assert_eq!(range.as_ref(), b"234");
+        assert_eq!(
+            server.requests_of(&bundle_path("ab00"))[0].range.as_deref(),
+            Some("bytes=2-4")
+        );
+
+        // a server without range support answers the whole bundle
+        server.ignore_ranges();
+        let range = api
+            .get_bundle_range(&Game::Dofus, "ab00", 2, 3)
+            .await
+            .unwrap();
+        assert_eq!(range.as_ref(), b"234");
+
+        assert!(matches!(
+            api.get_bundle_range(&Game::Dofus, "ab00", 8, 3).await,
+            Err(Error::TruncatedBundle { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn failures_stop_after_the_retries() {
+        let server = Server::start().await;
+        server.fail(usize::MAX);
+        let api = Api::with_url(&server.url).with_retry(retry());
 
-        Ok(res.bytes().await?)
+        assert!(matches!(
+            api.get_cytrus().await,
+            Err(Error::HttpStatus { status, .. }) if status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+        assert_eq!(server.requests().len(), 3);
     }
 }
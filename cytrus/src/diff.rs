@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::{download::to_hex, manifiest_generated::Manifest};
+
+struct FileInfo {
+    size: i64,
+    hash: String,
+    executable: bool,
+    symlink: Option<String>,
+}
+
+type Files = BTreeMap<String, FileInfo>;
+
+fn fragments(manifest: &Manifest) -> BTreeMap<String, Files> {
+    let mut fragments = BTreeMap::new();
+
+    for fragment in manifest.fragments().unwrap_or_default() {
+        let files: &mut Files = fragments
+            .entry(fragment.name().unwrap_or_default().to_string())
+            .or_default();
+
+        for file in fragment.files().unwrap_or_default() {
+            files.insert(
+                file.name().unwrap_or_default().to_string(),
+                FileInfo {
+                    size: file.size_(),
+                    hash: to_hex(file.hash().unwrap_or_default().bytes()),
+                    executable: file.executable(),
+                    symlink: file
+                        .symlink()
+                        .filter(|symlink| !symlink.is_empty())
+                        .map(String::from),
+                },
+            );
+        }
+    }
+
+    fragments
+}
+
+#[derive(Serialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub size: i64,
+}
+
+#[derive(Serialize)]
+pub struct Change<T> {
+    pub name: String,
+    pub from: T,
+    pub to: T,
+}
+
+#[derive(Serialize)]
+pub struct FragmentDiff {
+    pub name: String,
+    pub added: Vec<FileEntry>,
+    pub removed: Vec<FileEntry>,
+    pub resized: Vec<Change<i64>>,
+    /// files with the same size but different content
+    pub rehashed: Vec<Change<String>>,
+    pub executable: Vec<Change<bool>>,
+    pub symlink: Vec<Change<Option<String>>>,
+    /// size difference in bytes
+    pub delta: i64,
+}
+
+#[derive(Serialize)]
+pub struct ManifestDiff {
+    /// fragments with at least one change
+    pub fragments: Vec<FragmentDiff>,
+    /// size difference in bytes
+    pub delta: i64,
+}
+
+impl FragmentDiff {
+    fn new(name: String, from: &Files, to: &Files) -> Self {
+        let mut diff = FragmentDiff {
+            name,
+            added: Vec::new(),
+            removed: Vec::new(),
+            resized: Vec::new(),
+            rehashed: Vec::new(),
+            executable: Vec::new(),
+            symlink: Vec::new(),
+            delta: 0,
+        };
+
+        for (name, old) in from {
+            let Some(new) = to.get(name) else {
+                diff.delta -= old.size;
+                diff.removed.push(FileEntry {
+                    name: name.clone(),
+                    size: old.size,
+                });
+                continue;
+            };
+
+            if old.size != new.size {
+                diff.delta += new.size - old.size;
+                diff.resized.push(Change {
+                    name: name.clone(),
+                    from: old.size,
+                    to: new.size,
+                });
+            } else if old.hash != new.hash {
+                diff.rehashed.push(Change {
+                    name: name.clone(),
+                    from: old.hash.clone(),
+                    to: new.hash.clone(),
+                });
+            }
+
+            if old.executable != new.executable {
+                diff.executable.push(Change {
+                    name: name.clone(),
+                    from: old.executable,
+                    to: new.executable,
+                });
+            }
+
+            if old.symlink != new.symlink {
+                diff.symlink.push(Change {
+                    name: name.clone(),
+                    from: old.symlink.clone(),
+                    to: new.symlink.clone(),
+                });
+            }
+        }
+
+        for (name, new) in to {
+            if !from.contains_key(name) {
+                diff.delta += new.size;
+                diff.added.push(FileEntry {
+                    name: name.clone(),
+                    size: new.size,
+                });
+            }
+        }
+
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.resized.is_empty()
+            && self.rehashed.is_empty()
+            && self.executable.is_empty()
+            && self.symlink.is_empty()
+    }
+}
+
+impl ManifestDiff {
+    pub fn new(from: &Manifest, to: &Manifest) -> Self {
+        let from = fragments(from);
+        let to = fragments(to);
+        let empty = Files::new();
+
+        let names: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+
+        let fragments: Vec<FragmentDiff> = names
+            .into_iter()
+            .map(|name| {
+                FragmentDiff::new(
+                    name.clone(),
+                    from.get(name).unwrap_or(&empty),
+                    to.get(name).unwrap_or(&empty),
+                )
+            })
+            .filter(|diff| !diff.is_empty())
+            .collect();
+
+        ManifestDiff {
+            delta: fragments.iter().map(|fragment| fragment.delta).sum(),
+            fragments,
+        }
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for fragment in &self.fragments {
+            writeln!(f, "{} ({:+} bytes)", fragment.name, fragment.delta)?;
+
+            for file in &fragment.added {
+                writeln!(f, "\t+ {} ({} bytes)", file.name, file.size)?;
+            }
+            for file in &fragment.removed {
+                writeln!(f, "\t- {} ({} bytes)", file.name, file.size)?;
+            }
+            for file in &fragment.resized {
+                writeln!(f, "\t~ {} ({} -> {} bytes)", file.name, file.from, file.to)?;
+            }
+            for file in &fragment.rehashed {
+                writeln!(f, "\t~ {} ({} -> {})", file.name, file.from, file.to)?;
+            }
+            for file in &fragment.executable {
+                writeln!(
+                    f,
+                    "\t* {} (executable {} -> {})",
+                    file.name, file.from, file.to
+                )?;
+            }
+            for file in &fragment.symlink {
+                writeln!(
+                    f,
+                    "\t@ {} (symlink {} -> {})",
+                    file.name,
+                    file.from.as_deref().unwrap_or("none"),
+                    file.to.as_deref().unwrap_or("none")
+                )?;
+            }
+        }
+
+        write!(f, "Total: {:+} bytes", self.delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        read_manifest,
+        testing::{manifest, TestFile},
+    };
+
+    #[test]
+    fn identical_manifests_have_no_changes() {
+        let test = manifest(vec![("main", vec![TestFile::new("a", b"a")])]);
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let diff = ManifestDiff::new(&manifest, &manifest);
+        assert!(diff.fragments.is_empty());
+        assert_eq!(diff.delta, 0);
+    }
+
+    #[test]
+    fn every_kind_of_change_is_reported() {
+        let from = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("removed", b"old"),
+                TestFile::new("resized", b"abc"),
+                TestFile::new("rehashed", b"abc"),
+                TestFile::new("game", b"game"),
+                TestFile::symlink("link", "resized"),
+            ],
+        )]);
+        let to = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("added", b"new file"),
+                TestFile::new("resized", b"abcdef"),
+                TestFile::new("rehashed", b"xyz"),
+                TestFile {
+                    executable: true,
+                    ..TestFile::new("game", b"game")
+                },
+                TestFile::symlink("link", "rehashed"),
+            ],
+        )]);
+
+        let diff = ManifestDiff::new(
+            &read_manifest(&from.data).unwrap(),
+            &read_manifest(&to.data).unwrap(),
+        );
+
+        assert_eq!(diff.fragments.len(), 1);
+        let fragment = &diff.fragments[0];
+
+        assert_eq!(fragment.name, "main");
+        assert_eq!(fragment.added[0].name, "added");
+        assert_eq!(fragment.removed[0].name, "removed");
+        assert_eq!(
+            (
+                fragment.resized[0].name.as_str(),
+                fragment.resized[0].from,
+                fragment.resized[0].to
+            ),
+            ("resized", 3, 6)
+        );
+        assert_eq!(fragment.rehashed[0].name, "rehashed");
+        assert_eq!(fragment.executable[0].name, "game");
+        assert_eq!(fragment.symlink[0].to.as_deref(), Some("rehashed"));
+
+        // 8 bytes added, 3 removed and 3 more for the resized file
+        assert_eq!(fragment.delta, 8);
+        assert_eq!(diff.delta, 8);
+    }
+
+    #[test]
+    fn added_and_removed_fragments_are_reported() {
+        let from = manifest(vec![("old", vec![TestFile::new("a", b"a")])]);
+        let to = manifest(vec![("new", vec![TestFile::new("b", b"bb")])]);
+
+        let diff = ManifestDiff::new(
+            &read_manifest(&from.data).unwrap(),
+            &read_manifest(&to.data).unwrap(),
+        );
+
+        let names: Vec<&str> = diff
+            .fragments
+            .iter()
+            .map(|fragment| fragment.name.as_str())
+            .collect();
+        assert_eq!(names, ["new", "old"]);
+        assert_eq!(diff.delta, 1);
+    }
+}
@@ -0,0 +1,2112 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    api::Api,
+    error::{Error, HashMismatch, Result},
+    filter::{select_fragments, Filter},
+    game::Game,
+    journal::Journal,
+    manifiest_generated::{File, Manifest},
+    progress::{Event, Reporter, Silent},
+    store::Store,
+    verify::{Reason, Report},
+};
+
+/// Where a chunk lives inside a bundle
+pub struct ChunkLocation {
+    pub bundle: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// A slice of a bundle that has to be written at `file_offset` of `path`
+#[derive(Clone)]
+struct ChunkWrite {
+    hash: String,
+    bundle_offset: u64,
+    size: u64,
+    file: String,
+    path: PathBuf,
+    file_offset: u64,
+}
+
+/// A chunk of a file, files without chunks are stored as a single chunk
+/// that shares the hash of the file
+pub struct FileChunk {
+    pub hash: String,
+    pub size: u64,
+    pub offset: u64,
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn hash_bytes(data: &[u8]) -> String {
+    to_hex(&Sha1::digest(data))
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha1::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Path of a file of the manifest inside `output`
+///
+/// A name that is empty, absolute or goes through `..` would write outside
+/// of `output` and is refused, so is a negative size or offset that would
+/// wrap once read as unsigned.
+pub fn file_path(output: &Path, file: &File) -> Result<PathBuf> {
+    let name = file.name().ok_or(Error::UnnamedFile)?;
+    let path = Path::new(name);
+
+    let safe = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        && path
+            .components()
+            .any(|component| matches!(component, Component::Normal(_)));
+    if !safe {
+        return Err(Error::UnsafePath {
+            file: name.to_string(),
+        });
+    }
+
+    let negative = file.size_() < 0
+        || file
+            .chunks()
+            .unwrap_or_default()
+            .iter()
+            .any(|chunk| chunk.size_() < 0 || chunk.offset() < 0);
+    if negative {
+        return Err(Error::NegativeSize {
+            file: name.to_string(),
+        });
+    }
+
+    Ok(output.join(path))
+}
+
+pub fn file_chunks(file: &File) -> Vec<FileChunk> {
+    match file.chunks() {
+        Some(chunks) if !chunks.is_empty() => chunks
+            .iter()
+            .map(|chunk| FileChunk {
+                hash: to_hex(chunk.hash().unwrap_or_default().bytes()),
+                size: chunk.size_() as u64,
+                offset: chunk.offset() as u64,
+            })
+            .collect(),
+        _ => vec![FileChunk {
+            hash: to_hex(file.hash().unwrap_or_default().bytes()),
+            size: file.size_() as u64,
+            offset: 0,
+        }],
+    }
+}
+
+pub fn chunk_locations(manifest: &Manifest) -> HashMap<String, ChunkLocation> {
+    let mut locations = HashMap::new();
+
+    for fragment in manifest.fragments().unwrap_or_default() {
+        for bundle in fragment.bundles().unwrap_or_default() {
+            let bundle_hash = to_hex(bundle.hash().unwrap_or_default().bytes());
+
+            for chunk in bundle.chunks().unwrap_or_default() {
+                locations.insert(
+                    to_hex(chunk.hash().unwrap_or_default().bytes()),
+                    ChunkLocation {
+                        bundle: bundle_hash.clone(),
+                        offset: chunk.offset() as u64,
+                        size: chunk.size_() as u64,
+                    },
+                );
+            }
+        }
+    }
+
+    locations
+}
+
+/// Size of every bundle, the end of its last chunk
+fn bundle_sizes(manifest: &Manifest) -> HashMap<String, u64> {
+    let mut sizes = HashMap::new();
+
+    for fragment in manifest.fragments().unwrap_or_default() {
+        for bundle in fragment.bundles().unwrap_or_default() {
+            let size = bundle
+                .chunks()
+                .unwrap_or_default()
+                .iter()
+                .map(|chunk| (chunk.offset() + chunk.size_()) as u64)
+                .max()
+                .unwrap_or_default();
+
+            sizes.insert(to_hex(bundle.hash().unwrap_or_default().bytes()), size);
+        }
+    }
+
+    sizes
+}
+
+/// Ranges of a bundle covering `ranges`, ranges that overlap or touch are
+/// merged so they are fetched with a single request
+fn merge_ranges(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// The parts of a bundle that were fetched, the whole bundle or the ranges
+/// holding the chunks that are needed
+struct BundleData {
+    /// offset in the bundle and content of every part
+    parts: Vec<(u64, Bytes)>,
+    /// the only part is the whole bundle
+    whole: bool,
+}
+
+impl BundleData {
+    /// Bytes that were fetched
+    fn len(&self) -> u64 {
+        self.parts.iter().map(|(_, data)| data.len() as u64).sum()
+    }
+
+    /// Content of a chunk, `None` when no part holds all of it
+    fn chunk(&self, chunk: &ChunkWrite) -> Option<Bytes> {
+        self.parts.iter().find_map(|(offset, data)| {
+            let start = chunk.bundle_offset.checked_sub(*offset)? as usize;
+            let end = start + chunk.size as usize;
+            (end <= data.len()).then(|| data.slice(start..end))
+        })
+    }
+}
+
+/// Checks the chunks of a bundle against the manifest, and the bundle against
+/// its name when all of it was fetched
+fn check_chunks(bundle: &str, data: &BundleData, chunks: &[ChunkWrite]) -> Result<()> {
+    if let (true, [(_, whole)]) = (data.whole, data.parts.as_slice()) {
+        let actual = hash_bytes(whole);
+        if actual != bundle {
+            return Err(HashMismatch::Bundle {
+                bundle: bundle.to_string(),
+                actual,
+            }
+            .into());
+        }
+    }
+
+    for chunk in chunks {
+        let slice = data.chunk(chunk).ok_or_else(|| Error::TruncatedBundle {
+            bundle: bundle.to_string(),
+        })?;
+
+        let actual = hash_bytes(&slice);
+        if actual != chunk.hash {
+            return Err(HashMismatch::Chunk {
+                file: chunk.file.clone(),
+                offset: chunk.file_offset,
+                expected: chunk.hash.clone(),
+                actual,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+impl ChunkWrite {
+    /// Range of the chunk in its bundle
+    fn range(&self) -> Range<u64> {
+        self.bundle_offset..self.bundle_offset + self.size
+    }
+}
+
+/// Writes the chunks of a bundle checked by `check_chunks`
+fn write_chunks(data: &BundleData, chunks: &[ChunkWrite]) -> Result<()> {
+    for chunk in chunks {
+        if let Some(slice) = data.chunk(chunk) {
+            write_at(&chunk.path, chunk.file_offset, &slice)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Files and chunks that are already on disk before a download starts
+#[derive(Default)]
+pub struct LocalState {
+    /// files that already match the manifest and are left untouched
+    unchanged: HashSet<String>,
+    /// files of the previous version that are not part of the manifest anymore
+    removed: Vec<String>,
+    /// chunks that may be copied from a file on disk instead of being fetched
+    chunks: HashMap<String, LocalChunk>,
+}
+
+/// A chunk that may be found at `offset` of a file on disk
+struct LocalChunk {
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+}
+
+impl LocalState {
+    /// Compares the manifest of the installed version with the new one,
+    /// the files are trusted to match the previous manifest
+    pub fn from_manifest(previous: &Manifest, manifest: &Manifest, output: &Path) -> Result<Self> {
+        let mut state = LocalState::default();
+
+        let mut hashes = HashMap::new();
+        for fragment in manifest.fragments().unwrap_or_default() {
+            for file in fragment.files().unwrap_or_default() {
+                hashes.insert(
+                    file.name().unwrap_or_default(),
+                    (file.hash().unwrap_or_default().bytes(), file.size_() as u64),
+                );
+            }
+        }
+
+        for fragment in previous.fragments().unwrap_or_default() {
+            for file in fragment.files().unwrap_or_default() {
+                let path = file_path(output, &file)?;
+                let name = file.name().unwrap_or_default();
+
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+
+                match hashes.get(name) {
+                    Some(&(hash, size))
+                        if hash == file.hash().unwrap_or_default().bytes()
+                            && size == metadata.len() =>
+                    {
+                        state.unchanged.insert(name.to_string());
+                    }
+                    Some(_) => {}
+                    None => state.removed.push(name.to_string()),
+                }
+
+                for chunk in file_chunks(&file) {
+                    state.chunks.insert(
+                        chunk.hash,
+                        LocalChunk {
+                            path: path.clone(),
+                            offset: chunk.offset,
+                            size: chunk.size,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Hashes the files of an install directory, the chunks of a changed
+    /// file are looked up at the offset they have in the new manifest
+    pub fn from_install(manifest: &Manifest, output: &Path) -> Result<Self> {
+        let mut state = LocalState::default();
+
+        for fragment in manifest.fragments().unwrap_or_default() {
+            for file in fragment.files().unwrap_or_default() {
+                let path = file_path(output, &file)?;
+                let name = file.name().unwrap_or_default();
+
+                let Ok(metadata) = fs::metadata(&path) else {
+                    continue;
+                };
+
+                if metadata.len() == file.size_() as u64
+                    && hash_file(&path)? == to_hex(file.hash().unwrap_or_default().bytes())
+                {
+                    state.unchanged.insert(name.to_string());
+                    continue;
+                }
+
+                for chunk in file_chunks(&file) {
+                    if chunk.offset + chunk.size <= metadata.len() {
+                        state.chunks.insert(
+                            chunk.hash,
+                            LocalChunk {
+                                path: path.clone(),
+                                offset: chunk.offset,
+                                size: chunk.size,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Trusts the files a verification found valid, the chunks of a
+    /// corrupted file are looked up at the offset they have in the manifest
+    pub fn from_report(manifest: &Manifest, output: &Path, report: &Report) -> Result<Self> {
+        let mut state = LocalState::default();
+
+        let missing: HashSet<&str> = report.missing.iter().map(String::as_str).collect();
+        let corrupted: HashMap<&str, &Reason> = report
+            .corrupted
+            .iter()
+            .map(|file| (file.name.as_str(), &file.reason))
+            .collect();
+
+        for fragment in manifest.fragments().unwrap_or_default() {
+            for file in fragment.files().unwrap_or_default() {
+                let path = file_path(output, &file)?;
+                let name = file.name().unwrap_or_default();
+
+                if missing.contains(name) {
+                    continue;
+                }
+
+                let Some(reason) = corrupted.get(name).filter(|reason| reason.is_content()) else {
+                    state.unchanged.insert(name.to_string());
+                    continue;
+                };
+
+                let len = match reason {
+                    Reason::Size { actual, .. } => *actual,
+                    _ => file.size_() as u64,
+                };
+
+                for chunk in file_chunks(&file) {
+                    if chunk.offset + chunk.size <= len {
+                        state.chunks.insert(
+                            chunk.hash,
+                            LocalChunk {
+                                path: path.clone(),
+                                offset: chunk.offset,
+                                size: chunk.size,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(state)
+    }
+}
+
+/// What a download would fetch and write
+#[derive(Default)]
+pub struct Plan {
+    /// files that would be written
+    pub files: usize,
+    /// files that are already up to date
+    pub unchanged: usize,
+    /// bundles that would be fetched
+    pub bundles: usize,
+    /// chunks that would be fetched
+    pub chunks: usize,
+    /// bytes of the bundles, or of the ranges of bundles, that would be
+    /// fetched
+    pub transfer: u64,
+    /// bytes of the chunks found on disk, in the journal or in the store
+    pub restored: u64,
+    /// bytes of the chunks that appear more than once but are fetched once
+    pub deduplicated: u64,
+    /// bytes of the files that would be written
+    pub written: u64,
+    /// bytes of the fetched chunks that would be added to the store
+    pub stored: u64,
+    /// bytes of the install once downloaded
+    pub disk_size: u64,
+}
+
+/// Closest ancestor of `path` that exists, the directory may not exist yet
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    let existing = path.ancestors().find(|path| path.exists())?;
+
+    if existing.as_os_str().is_empty() {
+        Some(Path::new("."))
+    } else {
+        Some(existing)
+    }
+}
+
+#[cfg(unix)]
+fn same_disk(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+/// Without a device id both are assumed to share a disk, the check is only
+/// stricter
+#[cfg(not(unix))]
+fn same_disk(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+impl Plan {
+    /// Fails when the files that would be written don't fit on the disk of
+    /// `output`, or the chunks added to the store on the disk of `store`
+    ///
+    /// When both live on the same disk it has to hold both.
+    pub fn check_space(&self, output: &Path, store: Option<&Path>) -> Result<()> {
+        let mut needs = vec![(output, self.written)];
+
+        if let Some(store) = store {
+            match (existing_ancestor(output), existing_ancestor(store)) {
+                (Some(a), Some(b)) if same_disk(a, b) => needs[0].1 += self.stored,
+                _ => needs.push((store, self.stored)),
+            }
+        }
+
+        for (path, needed) in needs {
+            let Some(existing) = existing_ancestor(path) else {
+                continue;
+            };
+
+            let available = fs2::available_space(existing)?;
+
+            if needed > available {
+                return Err(Error::NoSpace {
+                    path: path.display().to_string(),
+                    needed,
+                    available,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Fetch {} chunks from {} bundles, {} bytes to transfer, {} bytes to store",
+            self.chunks, self.bundles, self.transfer, self.stored
+        )?;
+        writeln!(
+            f,
+            "Reuse {} bytes from disk or the store, save {} bytes of duplicated chunks",
+            self.restored, self.deduplicated
+        )?;
+        write!(
+            f,
+            "Write {} files, {} bytes, {} files are up to date, {} bytes on disk once done",
+            self.files, self.written, self.unchanged, self.disk_size
+        )
+    }
+}
+
+/// Outcome of a download
+pub struct Summary {
+    /// files that were written
+    pub files: usize,
+    /// files that were already up to date
+    pub unchanged: usize,
+    /// bundles that were fetched
+    pub bundles: usize,
+}
+
+/// A file being rebuilt in `part` before replacing `path`
+struct PendingFile<'a> {
+    name: &'a str,
+    hash: String,
+    size: u64,
+    executable: bool,
+    path: PathBuf,
+    part: PathBuf,
+    /// every chunk of the file with the bundle holding it
+    chunks: Vec<(&'a str, ChunkWrite)>,
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Copies a chunk found on disk into `part`, returns false when the data on
+/// disk doesn't match the chunk hash
+fn copy_local(local: &LocalChunk, hash: &str, part: &Path, offset: u64) -> Result<bool> {
+    let mut data = vec![0; local.size as usize];
+
+    let read = fs::File::open(&local.path).and_then(|mut file| {
+        file.seek(SeekFrom::Start(local.offset))?;
+        file.read_exact(&mut data)
+    });
+
+    if read.is_err() || hash_bytes(&data) != hash {
+        return Ok(false);
+    }
+
+    write_at(part, offset, &data)?;
+
+    Ok(true)
+}
+
+fn write_at(path: &Path, offset: u64, data: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(data)?;
+
+    Ok(())
+}
+
+/// Resolves `.` and `..` without touching the file system, a path that
+/// climbs above where it starts keeps its leading `..`
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if normalized.ends_with("..") || !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Name a path relative to the install has in the manifest
+fn manifest_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Names of the symlinks of the manifest
+fn manifest_symlinks<'a>(manifest: &Manifest<'a>) -> HashSet<&'a str> {
+    manifest
+        .fragments()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|fragment| fragment.files().unwrap_or_default().iter())
+        .filter(|file| file.symlink().is_some_and(|target| !target.is_empty()))
+        .filter_map(|file| file.name())
+        .collect()
+}
+
+/// Resolves the target of the symlink `name` of the manifest to the name of
+/// the file it points to
+///
+/// Fails when the target is absolute, climbs out of the install or goes
+/// through a directory that is one of the `symlinks` of the manifest, the
+/// file system would follow that symlink where the text of the path doesn't.
+fn check_symlink(name: &str, target: &str, symlinks: &HashSet<&str>) -> Result<String> {
+    let unsafe_symlink = || Error::UnsafeSymlink {
+        file: name.to_string(),
+        target: target.to_string(),
+    };
+
+    if Path::new(target).has_root() {
+        return Err(unsafe_symlink());
+    }
+
+    let path = Path::new(name)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(target);
+    let components: Vec<Component> = path.components().collect();
+
+    let mut directory = PathBuf::new();
+    for component in &components[..components.len().saturating_sub(1)] {
+        directory.push(component);
+
+        if symlinks.contains(manifest_name(&normalize(&directory)).as_str()) {
+            return Err(unsafe_symlink());
+        }
+    }
+
+    let resolved = normalize(&path);
+    if resolved.starts_with("..") {
+        return Err(unsafe_symlink());
+    }
+
+    Ok(manifest_name(&resolved))
+}
+
+/// Symlinks followed before giving up on resolving a file
+const MAX_SYMLINKS: usize = 40;
+
+/// Finds the file `name` of the manifest, a symlink is followed to the file
+/// of the manifest it points to
+fn find_file<'a>(manifest: &Manifest<'a>, name: &str) -> Result<File<'a>> {
+    let files: HashMap<&str, File<'a>> = manifest
+        .fragments()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|fragment| fragment.files().unwrap_or_default().iter())
+        .filter_map(|file| Some((file.name()?, file)))
+        .collect();
+    let symlinks = manifest_symlinks(manifest);
+
+    let mut name = name.to_string();
+
+    for _ in 0..MAX_SYMLINKS {
+        let file = *files
+            .get(name.as_str())
+            .ok_or_else(|| Error::MissingFile { file: name.clone() })?;
+
+        let Some(target) = file.symlink().filter(|target| !target.is_empty()) else {
+            return Ok(file);
+        };
+
+        name = check_symlink(&name, target, &symlinks)?;
+    }
+
+    Err(Error::SymlinkLoop { file: name })
+}
+
+/// Points the symlink at `path` to `target`, returns false when it already
+/// did
+fn create_symlink(path: &Path, target: &str) -> Result<bool> {
+    if fs::read_link(path).is_ok_and(|current| current == Path::new(target)) {
+        return Ok(false);
+    }
+
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)?;
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, path)?;
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_file(target, path)?;
+
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    fs::set_permissions(path, permissions)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Rebuilds the files of a manifest from the bundles of a game
+pub struct Downloader {
+    api: Api,
+    game: Game,
+    /// bundles fetched at the same time
+    concurrency: usize,
+    store: Option<Store>,
+    /// fragments to rebuild, all of them when empty
+    fragments: Vec<String>,
+    filter: Filter,
+    reporter: Arc<dyn Reporter>,
+}
+
+impl Downloader {
+    pub fn new(api: Api, game: Game, concurrency: usize) -> Self {
+        Downloader {
+            api,
+            game,
+            concurrency: concurrency.max(1),
+            store: None,
+            fragments: Vec::new(),
+            filter: Filter::default(),
+            reporter: Arc::new(Silent),
+        }
+    }
+
+    /// Only rebuilds the files matching `filter`, the bundles or the ranges
+    /// of bundles holding only the other files are never fetched
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Only rebuilds the files of `fragments`
+    pub fn with_fragments(mut self, fragments: Vec<String>) -> Self {
+        self.fragments = fragments;
+        self
+    }
+
+    /// Files of the selected fragments that match the filter
+    fn selected_files<'a>(&self, manifest: &Manifest<'a>) -> Result<Vec<File<'a>>> {
+        Ok(select_fragments(manifest, &self.fragments)?
+            .iter()
+            .flat_map(|fragment| fragment.files().unwrap_or_default().iter())
+            .filter(|file| self.filter.matches(file.name().unwrap_or_default()))
+            .collect())
+    }
+
+    /// Reads chunks from `store` before fetching them and keeps every
+    /// fetched chunk in it
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn store(&self) -> Option<&Store> {
+        self.store.as_ref()
+    }
+
+    /// Sends the progress of every download to `reporter`
+    pub fn with_reporter(mut self, reporter: impl Reporter + 'static) -> Self {
+        self.reporter = Arc::new(reporter);
+        self
+    }
+
+    /// Writes a chunk that is in the journal, on disk or in the store,
+    /// returns false when the chunk has to be fetched
+    fn restore_chunk(
+        &self,
+        write: &ChunkWrite,
+        local: &LocalState,
+        journal: &Journal,
+        resume: bool,
+    ) -> Result<bool> {
+        if resume && journal.has_chunk(&write.file, write.file_offset, &write.hash) {
+            return Ok(true);
+        }
+
+        if let Some(local) = local.chunks.get(&write.hash) {
+            if copy_local(local, &write.hash, &write.path, write.file_offset)? {
+                return Ok(true);
+            }
+        }
+
+        if let Some(data) = match &self.store {
+            Some(store) => store.get(&write.hash)?,
+            None => None,
+        } {
+            write_at(&write.path, write.file_offset, &data)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Fetches the ranges of a bundle of `size` bytes, the whole bundle when
+    /// the ranges cover all of it
+    async fn fetch_ranges(
+        &self,
+        bundle: &str,
+        size: u64,
+        ranges: &[Range<u64>],
+    ) -> Result<BundleData> {
+        if matches!(ranges, [range] if *range == (0..size)) {
+            let data = self.api.get_bundle(&self.game, bundle).await?;
+            return Ok(BundleData {
+                parts: vec![(0, data)],
+                whole: true,
+            });
+        }
+
+        let mut parts = Vec::new();
+        for range in ranges {
+            let data = self
+                .api
+                .get_bundle_range(&self.game, bundle, range.start, range.end - range.start)
+                .await?;
+            parts.push((range.start, data));
+        }
+
+        Ok(BundleData {
+            parts,
+            whole: false,
+        })
+    }
+
+    /// Fetches the chunks of a bundle of `size` bytes and checks them against
+    /// the manifest, they are fetched a second time if one doesn't match
+    ///
+    /// Only the ranges holding `chunks` are fetched, so a file left out by
+    /// the filter or the fragments costs no bandwidth.
+    async fn fetch_bundle(
+        &self,
+        bundle: &str,
+        size: u64,
+        chunks: &[ChunkWrite],
+    ) -> Result<BundleData> {
+        self.reporter.report(&Event::BundleStarted {
+            bundle: bundle.to_string(),
+        });
+
+        let ranges = merge_ranges(chunks.iter().map(ChunkWrite::range).collect());
+        let mut data = self.fetch_ranges(bundle, size, &ranges).await?;
+
+        if check_chunks(bundle, &data, chunks).is_err() {
+            data = self.fetch_ranges(bundle, size, &ranges).await?;
+            check_chunks(bundle, &data, chunks)?;
+        }
+
+        self.reporter.report(&Event::BundleFinished {
+            bundle: bundle.to_string(),
+            size: data.len(),
+        });
+
+        Ok(data)
+    }
+
+    /// Reads a chunk from the store or fetches its range of the bundle, the
+    /// range is fetched a second time if it doesn't match the chunk
+    async fn read_chunk(
+        &self,
+        file: &str,
+        chunk: &FileChunk,
+        location: &ChunkLocation,
+    ) -> Result<Bytes> {
+        if let Some(data) = match &self.store {
+            Some(store) => store.get(&chunk.hash)?,
+            None => None,
+        } {
+            return Ok(data.into());
+        }
+
+        let size = location.size.min(chunk.size);
+        let fetch = || {
+            self.api
+                .get_bundle_range(&self.game, &location.bundle, location.offset, size)
+        };
+
+        let mut data = fetch().await?;
+        let mut actual = hash_bytes(&data);
+
+        if actual != chunk.hash {
+            data = fetch().await?;
+            actual = hash_bytes(&data);
+        }
+
+        if actual != chunk.hash {
+            return Err(HashMismatch::Chunk {
+                file: file.to_string(),
+                offset: chunk.offset,
+                expected: chunk.hash.clone(),
+                actual,
+            }
+            .into());
+        }
+
+        Ok(data)
+    }
+
+    /// Checks the install in `output` against the selected files of the
+    /// manifest, reporting every checked file
+    pub fn verify(&self, manifest: &Manifest<'_>, output: &Path) -> Result<Report> {
+        Report::check(
+            manifest,
+            output,
+            &self.fragments,
+            &self.filter,
+            self.reporter.as_ref(),
+        )
+    }
+
+    /// Writes the content of the file `name` of the manifest to `out` and
+    /// returns its size, a symlink is followed inside the manifest
+    ///
+    /// Only the ranges of the bundles holding its chunks are fetched, in
+    /// order and `concurrency` at a time. Chunks of the store are read from
+    /// it but nothing is written to disk.
+    pub async fn cat(
+        &self,
+        manifest: &Manifest<'_>,
+        name: &str,
+        out: &mut impl Write,
+    ) -> Result<u64> {
+        let file = find_file(manifest, name)?;
+        let name = file.name().ok_or(Error::UnnamedFile)?;
+        let hash = to_hex(file.hash().unwrap_or_default().bytes());
+        let size = file.size_() as u64;
+
+        let mut chunks = if size > 0 {
+            file_chunks(&file)
+        } else {
+            Vec::new()
+        };
+        chunks.sort_by_key(|chunk| chunk.offset);
+
+        let locations = chunk_locations(manifest);
+        let mut reads = Vec::new();
+        let mut bundles = HashSet::new();
+        let mut transfer = 0;
+
+        for chunk in chunks {
+            let location = locations
+                .get(&chunk.hash)
+                .ok_or_else(|| Error::MissingChunk {
+                    file: name.to_string(),
+                    chunk: chunk.hash.clone(),
+                })?;
+
+            let stored = self
+                .store
+                .as_ref()
+                .is_some_and(|store| store.contains(&chunk.hash));
+            if !stored {
+                bundles.insert(location.bundle.as_str());
+                transfer += location.size.min(chunk.size);
+            }
+
+            reads.push((chunk, location, stored));
+        }
+
+        self.reporter.report(&Event::Planned {
+            files: 1,
+            bundles: bundles.len(),
+            transfer,
+        });
+
+        let mut data = stream::iter(&reads)
+            .map(|(chunk, location, stored)| async move {
+                Ok::<_, Error>((self.read_chunk(name, chunk, location).await?, *stored))
+            })
+            .buffered(self.concurrency);
+
+        let mut hasher = Sha1::new();
+        let mut transferred = 0;
+
+        while let Some(result) = data.next().await {
+            let (data, stored) = result?;
+            hasher.update(&data);
+            out.write_all(&data)?;
+
+            if !stored {
+                transferred += data.len() as u64;
+                self.reporter
+                    .report(&Event::Transferred { bytes: transferred });
+            }
+        }
+
+        out.flush()?;
+
+        let actual = to_hex(&hasher.finalize());
+        self.reporter.report(&Event::Verified {
+            file: name.to_string(),
+            valid: actual == hash,
+            retrying: false,
+        });
+
+        if actual != hash {
+            return Err(HashMismatch::File {
+                file: name.to_string(),
+                expected: hash,
+                actual,
+            }
+            .into());
+        }
+
+        self.reporter.report(&Event::FileCompleted {
+            file: name.to_string(),
+            size,
+        });
+        self.reporter.report(&Event::Finished {
+            files: 1,
+            unchanged: 0,
+            bundles: bundles.len(),
+        });
+
+        Ok(size)
+    }
+
+    /// Works out what `update` would fetch and write without touching the
+    /// disk, a chunk found on disk or in the store is trusted to match its
+    /// hash
+    pub fn plan(
+        &self,
+        manifest: &Manifest<'_>,
+        output: &Path,
+        local: &LocalState,
+        journal: &Journal,
+    ) -> Result<Plan> {
+        let locations = chunk_locations(manifest);
+
+        let mut plan = Plan::default();
+        let mut ranges: HashMap<&str, Vec<Range<u64>>> = HashMap::new();
+        let mut fetched = HashSet::new();
+
+        for file in self.selected_files(manifest)? {
+            let name = file.name().ok_or(Error::UnnamedFile)?;
+            let path = file_path(output, &file)?;
+
+            if let Some(target) = file.symlink().filter(|target| !target.is_empty()) {
+                if fs::read_link(&path).is_ok_and(|current| current == Path::new(target)) {
+                    plan.unchanged += 1;
+                } else {
+                    plan.files += 1;
+                }
+                continue;
+            }
+
+            let hash = to_hex(file.hash().unwrap_or_default().bytes());
+            let size = file.size_() as u64;
+            plan.disk_size += size;
+
+            let done = journal.has_file(name, &hash)
+                && fs::metadata(&path).is_ok_and(|metadata| metadata.len() == size);
+
+            if done || local.unchanged.contains(name) {
+                plan.unchanged += 1;
+                continue;
+            }
+
+            plan.files += 1;
+            plan.written += size;
+
+            if size == 0 {
+                continue;
+            }
+
+            let resume = journal.has_chunks(name)
+                && fs::metadata(part_path(&path)).is_ok_and(|metadata| metadata.len() == size);
+
+            for chunk in file_chunks(&file) {
+                let location = locations
+                    .get(&chunk.hash)
+                    .ok_or_else(|| Error::MissingChunk {
+                        file: name.to_string(),
+                        chunk: chunk.hash.clone(),
+                    })?;
+                let size = location.size.min(chunk.size);
+
+                let restored = (resume && journal.has_chunk(name, chunk.offset, &chunk.hash))
+                    || local.chunks.contains_key(&chunk.hash)
+                    || self
+                        .store
+                        .as_ref()
+                        .is_some_and(|store| store.contains(&chunk.hash));
+
+                if restored {
+                    plan.restored += size;
+                } else if fetched.insert(chunk.hash) {
+                    plan.chunks += 1;
+                    if self.store.is_some() {
+                        plan.stored += size;
+                    }
+                    ranges
+                        .entry(location.bundle.as_str())
+                        .or_default()
+                        .push(location.offset..location.offset + size);
+                } else {
+                    plan.deduplicated += size;
+                }
+            }
+        }
+
+        plan.bundles = ranges.len();
+        plan.transfer = ranges
+            .into_values()
+            .flat_map(merge_ranges)
+            .map(|range| range.end - range.start)
+            .sum();
+
+        Ok(plan)
+    }
+
+    /// Rebuilds every file of the manifest inside `output` by fetching the
+    /// bundles that hold their chunks
+    pub async fn download(
+        &self,
+        manifest: &Manifest<'_>,
+        output: &Path,
+        journal: &mut Journal,
+    ) -> Result<Summary> {
+        self.update(manifest, output, &LocalState::default(), journal)
+            .await
+    }
+
+    /// Rebuilds the files of the manifest that don't match `local`, only the
+    /// chunks that can't be found on disk, in the journal or in the store are
+    /// fetched
+    ///
+    /// Bundles are fetched concurrently but their chunks are written in
+    /// order, one bundle at a time
+    pub async fn update(
+        &self,
+        manifest: &Manifest<'_>,
+        output: &Path,
+        local: &LocalState,
+        journal: &mut Journal,
+    ) -> Result<Summary> {
+        let files = self.selected_files(manifest)?;
+        let plan = self.plan(manifest, output, local, journal)?;
+        plan.check_space(output, self.store.as_ref().map(Store::root))?;
+
+        self.reporter.report(&Event::Planned {
+            files: plan.files,
+            bundles: plan.bundles,
+            transfer: plan.transfer,
+        });
+
+        let locations = chunk_locations(manifest);
+        let bundle_sizes = bundle_sizes(manifest);
+        let manifest_symlinks = manifest_symlinks(manifest);
+
+        let mut writes: BTreeMap<&str, Vec<ChunkWrite>> = BTreeMap::new();
+        let mut pending = Vec::new();
+        let mut symlinks = Vec::new();
+        let mut unchanged = 0;
+
+        for file in files {
+            let name = file.name().ok_or(Error::UnnamedFile)?;
+            let path = file_path(output, &file)?;
+
+            if let Some(target) = file.symlink().filter(|target| !target.is_empty()) {
+                check_symlink(name, target, &manifest_symlinks)?;
+                symlinks.push((path, target));
+                continue;
+            }
+
+            let hash = to_hex(file.hash().unwrap_or_default().bytes());
+            let size = file.size_() as u64;
+            let part = part_path(&path);
+
+            let done = journal.has_file(name, &hash)
+                && fs::metadata(&path).is_ok_and(|metadata| metadata.len() == size);
+
+            if done || local.unchanged.contains(name) {
+                if file.executable() {
+                    set_executable(&path)?;
+                }
+
+                unchanged += 1;
+                continue;
+            }
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // the chunks of the journal are only trusted if the .part is intact
+            let resume = journal.has_chunks(name)
+                && fs::metadata(&part).is_ok_and(|metadata| metadata.len() == size);
+
+            if !resume {
+                fs::File::create(&part)?.set_len(size)?;
+            }
+
+            let mut chunks = Vec::new();
+
+            if size > 0 {
+                for chunk in file_chunks(&file) {
+                    let location =
+                        locations
+                            .get(&chunk.hash)
+                            .ok_or_else(|| Error::MissingChunk {
+                                file: name.to_string(),
+                                chunk: chunk.hash.clone(),
+                            })?;
+
+                    let write = ChunkWrite {
+                        hash: chunk.hash,
+                        bundle_offset: location.offset,
+                        size: location.size.min(chunk.size),
+                        file: name.to_string(),
+                        path: part.clone(),
+                        file_offset: chunk.offset,
+                    };
+
+                    if !self.restore_chunk(&write, local, journal, resume)? {
+                        writes
+                            .entry(&location.bundle)
+                            .or_default()
+                            .push(write.clone());
+                    }
+
+                    chunks.push((location.bundle.as_str(), write));
+                }
+            }
+
+            pending.push(PendingFile {
+                name,
+                hash,
+                size,
+                executable: file.executable(),
+                path,
+                part,
+                chunks,
+            });
+        }
+
+        let bundle_size = |bundle: &str| bundle_sizes.get(bundle).copied().unwrap_or_default();
+
+        let mut bundles = stream::iter(&writes)
+            .map(|(bundle, chunks)| async move {
+                let data = self
+                    .fetch_bundle(bundle, bundle_size(bundle), chunks)
+                    .await?;
+                Ok::<_, Error>((data, chunks))
+            })
+            .buffered(self.concurrency);
+
+        let mut transferred = 0;
+
+        while let Some(result) = bundles.next().await {
+            let (data, chunks) = result?;
+            write_chunks(&data, chunks)?;
+
+            transferred += data.len();
+            self.reporter
+                .report(&Event::Transferred { bytes: transferred });
+
+            for chunk in chunks {
+                if let (Some(store), Some(slice)) = (&self.store, data.chunk(chunk)) {
+                    store.insert(&chunk.hash, &slice)?;
+                }
+
+                journal.add_chunk(&chunk.file, chunk.file_offset, &chunk.hash)?;
+            }
+        }
+
+        for file in &pending {
+            let valid = file.size == 0 || hash_file(&file.part)? == file.hash;
+            self.reporter.report(&Event::Verified {
+                file: file.name.to_string(),
+                valid,
+                retrying: !valid,
+            });
+
+            if !valid {
+                // refetch every chunk of the file once before giving up
+                let mut refetch: BTreeMap<&str, Vec<ChunkWrite>> = BTreeMap::new();
+                for (bundle, chunk) in &file.chunks {
+                    refetch.entry(bundle).or_default().push(chunk.clone());
+                }
+
+                for (bundle, chunks) in &refetch {
+                    let data = self
+                        .fetch_bundle(bundle, bundle_size(bundle), chunks)
+                        .await?;
+                    write_chunks(&data, chunks)?;
+
+                    transferred += data.len();
+                    self.reporter
+                        .report(&Event::Transferred { bytes: transferred });
+                }
+
+                let actual = hash_file(&file.part)?;
+                self.reporter.report(&Event::Verified {
+                    file: file.name.to_string(),
+                    valid: actual == file.hash,
+                    retrying: false,
+                });
+
+                if actual != file.hash {
+                    return Err(HashMismatch::File {
+                        file: file.name.to_string(),
+                        expected: file.hash.clone(),
+                        actual,
+                    }
+                    .into());
+                }
+            }
+
+            fs::rename(&file.part, &file.path)?;
+
+            if file.executable {
+                set_executable(&file.path)?;
+            }
+
+            journal.add_file(file.name, &file.hash)?;
+
+            self.reporter.report(&Event::FileCompleted {
+                file: file.name.to_string(),
+                size: file.size,
+            });
+        }
+
+        let mut created = 0;
+        for (path, target) in &symlinks {
+            if create_symlink(path, target)? {
+                created += 1;
+            }
+        }
+
+        for name in &local.removed {
+            let path = output.join(name);
+            if path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        let summary = Summary {
+            files: pending.len() + created,
+            unchanged: unchanged + symlinks.len() - created,
+            bundles: writes.len(),
+        };
+
+        self.reporter.report(&Event::Finished {
+            files: summary.files,
+            unchanged: summary.unchanged,
+            bundles: summary.bundles,
+        });
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        api::RetryPolicy,
+        read_manifest,
+        testing::{bundle_path, manifest, Server, TestFile, TestManifest},
+    };
+
+    /// Downloader of the bundles served by `server`, failed requests are not
+    /// retried
+    fn downloader(server: &Server) -> Downloader {
+        let retry = RetryPolicy {
+            retries: 0,
+            ..RetryPolicy::default()
+        };
+
+        Downloader::new(Api::with_url(&server.url).with_retry(retry), Game::Dofus, 2)
+    }
+
+    /// Served manifest holding `a.txt` and the symlink `link` to it
+    async fn cat_server() -> (Server, TestManifest) {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.txt", b"world"),
+                TestFile::symlink("link", "a.txt"),
+            ],
+        )]);
+        let server = Server::start().await;
+        server.route_manifest("2.0", &test);
+
+        (server, test)
+    }
+
+    #[tokio::test]
+    async fn files_are_read_from_ranges_of_their_bundle() {
+        let (server, test) = cat_server().await;
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let mut out = Vec::new();
+        let size = downloader(&server)
+            .cat(&manifest, "b.txt", &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(size, 5);
+        assert_eq!(out, b"world");
+        assert!(server
+            .requests()
+            .iter()
+            .all(|request| request.range.as_deref() == Some("bytes=5-9")));
+    }
+
+    #[tokio::test]
+    async fn files_are_read_from_servers_without_range_support() {
+        let (server, test) = cat_server().await;
+        server.ignore_ranges();
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let mut out = Vec::new();
+        downloader(&server)
+            .cat(&manifest, "b.txt", &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(out, b"world");
+    }
+
+    #[tokio::test]
+    async fn symlinks_are_read_from_their_target() {
+        let (server, test) = cat_server().await;
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let mut out = Vec::new();
+        downloader(&server)
+            .cat(&manifest, "link", &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(out, b"hello");
+    }
+
+    #[tokio::test]
+    async fn files_that_dont_match_their_chunks_are_not_read() {
+        let (server, test) = cat_server().await;
+        let manifest = read_manifest(&test.data).unwrap();
+        let (bundle, _) = &test.bundles[0];
+        server.route(&bundle_path(bundle), b"jello world");
+
+        let mut out = Vec::new();
+        let result = downloader(&server).cat(&manifest, "a.txt", &mut out).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::HashMismatch(HashMismatch::Chunk { file, .. })) if file == "a.txt"
+        ));
+        // the chunk is fetched again once before the read fails
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 2);
+    }
+
+    /// Path of the only file of a manifest holding `file` inside `out`
+    fn path_of(file: TestFile) -> Result<PathBuf> {
+        let test = manifest(vec![("main", vec![file])]);
+        let manifest = crate::read_manifest(&test.data).unwrap();
+        let file = manifest.fragments().unwrap().get(0).files().unwrap().get(0);
+
+        file_path(Path::new("out"), &file)
+    }
+
+    #[test]
+    fn file_names_inside_the_install_are_joined() {
+        assert_eq!(
+            path_of(TestFile::new("a.txt", b"a")).unwrap(),
+            Path::new("out/a.txt")
+        );
+        assert_eq!(
+            path_of(TestFile::new("bin/./game", b"a")).unwrap(),
+            Path::new("out/bin/game")
+        );
+    }
+
+    #[test]
+    fn file_names_leaving_the_install_are_refused() {
+        for name in ["/tmp/x", "../x", "a/../../x", "a/../b", "", "."] {
+            assert!(
+                matches!(
+                    path_of(TestFile::new(name, b"a")),
+                    Err(Error::UnsafePath { .. })
+                ),
+                "{name} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn negative_sizes_are_refused() {
+        let file = TestFile {
+            size: -1,
+            ..TestFile::new("a.txt", b"a")
+        };
+
+        assert!(matches!(path_of(file), Err(Error::NegativeSize { .. })));
+    }
+
+    #[test]
+    fn normalize_resolves_dots() {
+        assert_eq!(normalize(Path::new("a/./b/../c")), Path::new("a/c"));
+        assert_eq!(normalize(Path::new(".")), Path::new(""));
+        assert_eq!(normalize(Path::new("a/../..")), Path::new(".."));
+        assert_eq!(normalize(Path::new("../../x")), Path::new("../../x"));
+        assert_eq!(normalize(Path::new("a/../../b/../c")), Path::new("../c"));
+    }
+
+    #[test]
+    fn symlinks_inside_the_install_resolve() {
+        let symlinks = HashSet::from(["link", "sub/link"]);
+
+        assert_eq!(
+            check_symlink("link", "bin/game", &symlinks).unwrap(),
+            "bin/game"
+        );
+        assert_eq!(
+            check_symlink("sub/link", "../a.txt", &symlinks).unwrap(),
+            "a.txt"
+        );
+        assert_eq!(
+            check_symlink("sub/link", "./b/../c", &symlinks).unwrap(),
+            "sub/c"
+        );
+        // a symlink to another symlink is checked when that one is created
+        assert_eq!(
+            check_symlink("sub/link", "../link", &symlinks).unwrap(),
+            "link"
+        );
+    }
+
+    #[test]
+    fn symlinks_leaving_the_install_are_refused() {
+        let symlinks = HashSet::from(["evil"]);
+
+        // the install root is not part of the check, `-o .` can't bypass it
+        assert!(check_symlink("evil", "../../etc/passwd", &symlinks).is_err());
+        assert!(check_symlink("evil", "..", &symlinks).is_err());
+        assert!(check_symlink("evil", "/etc/passwd", &symlinks).is_err());
+        assert!(check_symlink("a/evil", "../../x/../y", &symlinks).is_err());
+    }
+
+    #[test]
+    fn symlinks_through_other_symlinks_are_refused() {
+        let symlinks = HashSet::from(["sub/up", "sub/evil"]);
+
+        assert_eq!(check_symlink("sub/up", "..", &symlinks).unwrap(), "");
+        assert!(check_symlink("sub/evil", "up/../../escaped-link", &symlinks).is_err());
+        assert!(check_symlink("sub/evil", "up/file", &symlinks).is_err());
+    }
+
+    #[tokio::test]
+    async fn corrupted_bundles_are_fetched_again() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.txt", b"world"),
+            ],
+        )]);
+        let (bundle, data) = &test.bundles[0];
+        let mut corrupted = data.clone();
+        corrupted[0] ^= 1;
+
+        let server = Server::start().await;
+        server.route_in_turn(&bundle_path(bundle), vec![corrupted, data.clone()]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let summary = downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 2);
+        assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"world");
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn bundles_corrupted_twice_are_errors() {
+        let test = manifest(vec![("main", vec![TestFile::new("a.txt", b"hello")])]);
+        let (bundle, data) = &test.bundles[0];
+        let mut corrupted = data.clone();
+        corrupted[0] ^= 1;
+
+        let server = Server::start().await;
+        server.route(&bundle_path(bundle), &corrupted);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let result = downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await;
+
+        match result {
+            Err(Error::HashMismatch(HashMismatch::Bundle { bundle: name, .. })) => {
+                assert_eq!(&name, bundle);
+            }
+            _ => panic!("the corrupted bundle was accepted"),
+        }
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 2);
+        assert!(!output.join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn ranges_corrupted_twice_are_errors() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.txt", b"world"),
+            ],
+        )]);
+        let (bundle, data) = &test.bundles[0];
+        let mut corrupted = data.clone();
+        corrupted[6] ^= 1;
+
+        let server = Server::start().await;
+        server.route(&bundle_path(bundle), &corrupted);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let result = downloader(&server)
+            .with_filter(Filter::new(&["b.txt".to_string()], &[]).unwrap())
+            .download(&manifest, &output, &mut journal)
+            .await;
+
+        match result {
+            Err(Error::HashMismatch(HashMismatch::Chunk { file, offset, .. })) => {
+                assert_eq!(file, "b.txt");
+                assert_eq!(offset, 0);
+            }
+            _ => panic!("the corrupted range was accepted"),
+        }
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn files_that_dont_match_are_fetched_again() {
+        let test = manifest(vec![("main", vec![TestFile::new("a.txt", b"hello")])]);
+        let (bundle, data) = &test.bundles[0];
+
+        let server = Server::start().await;
+        server.route(&bundle_path(bundle), data);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+
+        // the journal trusts a .part that was damaged after its chunk was
+        // written, only the hash of the whole file catches it
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("a.txt.part"), b"hxllo").unwrap();
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal
+            .add_chunk("a.txt", 0, &hash_bytes(b"hello"))
+            .unwrap();
+
+        let summary = downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 1);
+        assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(server.requests_of(&bundle_path(bundle)).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn updates_from_a_manifest_fetch_what_changed() {
+        let previous = manifest(vec![
+            ("kept", vec![TestFile::new("a.txt", b"same")]),
+            ("changed", vec![TestFile::new("b.txt", b"old")]),
+            ("gone", vec![TestFile::new("c.txt", b"removed")]),
+        ]);
+        let next = manifest(vec![
+            ("kept", vec![TestFile::new("a.txt", b"same")]),
+            ("changed", vec![TestFile::new("b.txt", b"new")]),
+        ]);
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &previous);
+        server.route_manifest("2.0", &next);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let previous = read_manifest(&previous.data).unwrap();
+        let manifest = read_manifest(&next.data).unwrap();
+        let downloader = downloader(&server);
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        downloader
+            .download(&previous, &output, &mut journal)
+            .await
+            .unwrap();
+
+        let local = LocalState::from_manifest(&previous, &manifest, &output).unwrap();
+        let mut journal = Journal::open(&output, "2.0").unwrap();
+        let summary = downloader
+            .update(&manifest, &output, &local, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.bundles, 1);
+        assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"same");
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"new");
+        // c.txt is not part of the new version anymore
+        assert!(!output.join("c.txt").exists());
+
+        let (kept, _) = &next.bundles[0];
+        let (changed, _) = &next.bundles[1];
+        assert_eq!(server.requests_of(&bundle_path(kept)).len(), 1);
+        assert_eq!(server.requests_of(&bundle_path(changed)).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn updates_from_an_install_reuse_the_chunks_on_disk() {
+        let test = manifest(vec![
+            (
+                "main",
+                vec![
+                    TestFile::new("a.txt", b"same"),
+                    TestFile::new("b.txt", b"grown"),
+                ],
+            ),
+            ("other", vec![TestFile::new("c.txt", b"missing")]),
+        ]);
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("a.txt"), b"same").unwrap();
+        // the chunk of b.txt is still at its offset
+        fs::write(output.join("b.txt"), b"grown and more").unwrap();
+
+        let manifest = read_manifest(&test.data).unwrap();
+        let local = LocalState::from_install(&manifest, &output).unwrap();
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let summary = downloader(&server)
+            .update(&manifest, &output, &local, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"grown");
+        assert_eq!(fs::read(output.join("c.txt")).unwrap(), b"missing");
+
+        let (main, _) = &test.bundles[0];
+        let (other, _) = &test.bundles[1];
+        assert!(server.requests_of(&bundle_path(main)).is_empty());
+        assert_eq!(server.requests_of(&bundle_path(other)).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn downloads_resume_from_the_journal() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"done"),
+                TestFile::new("b.txt", b"written"),
+            ],
+        )]);
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+
+        // killed after a.txt was renamed and the chunk of b.txt written
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("a.txt"), b"done").unwrap();
+        fs::write(output.join("b.txt.part"), b"written").unwrap();
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_file("a.txt", &hash_bytes(b"done")).unwrap();
+        journal
+            .add_chunk("b.txt", 0, &hash_bytes(b"written"))
+            .unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let summary = downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 1);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.bundles, 0);
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"written");
+        assert!(!output.join("b.txt.part").exists());
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn journals_of_another_version_are_ignored() {
+        let test = manifest(vec![("main", vec![TestFile::new("a.txt", b"new")])]);
+
+        let server = Server::start().await;
+        server.route_manifest("2.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("a.txt.part"), b"old").unwrap();
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_chunk("a.txt", 0, &hash_bytes(b"new")).unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&output, "2.0").unwrap();
+        downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"new");
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn touching_ranges_are_merged() {
+        assert_eq!(
+            merge_ranges(vec![8..10, 0..4, 4..6, 2..3]),
+            vec![0..6, 8..10]
+        );
+        assert!(merge_ranges(Vec::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn filtered_files_are_not_fetched() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.log", b"skipped"),
+                TestFile::new("c.txt", b"world"),
+            ],
+        )]);
+        let (bundle, _) = &test.bundles[0];
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+        let downloader =
+            downloader(&server).with_filter(Filter::new(&["*.txt".to_string()], &[]).unwrap());
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let plan = downloader
+            .plan(&manifest, &output, &LocalState::default(), &journal)
+            .unwrap();
+        assert_eq!(plan.transfer, 10);
+
+        downloader
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(output.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(output.join("c.txt")).unwrap(), b"world");
+        assert!(!output.join("b.log").exists());
+
+        let ranges: Vec<_> = server
+            .requests_of(&bundle_path(bundle))
+            .into_iter()
+            .map(|request| request.range)
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                Some("bytes=0-4".to_string()),
+                Some("bytes=12-16".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn whole_bundles_are_fetched_without_ranges() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.txt", b"world"),
+            ],
+        )]);
+        let (bundle, _) = &test.bundles[0];
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        downloader(&server)
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        let requests = server.requests_of(&bundle_path(bundle));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].range, None);
+    }
+
+    #[tokio::test]
+    async fn downloads_are_reported_in_order() {
+        let test = manifest(vec![("main", vec![TestFile::new("a.txt", b"hello")])]);
+        let (bundle, _) = &test.bundles[0];
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let reported = events.clone();
+        let downloader = downloader(&server)
+            .with_reporter(move |event: &Event| reported.lock().unwrap().push(event.clone()));
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        downloader
+            .download(&manifest, &output, &mut journal)
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        let names: Vec<String> = events
+            .iter()
+            .map(|event| {
+                serde_json::to_value(event).unwrap()["event"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "planned",
+                "bundle_started",
+                "bundle_finished",
+                "transferred",
+                "verified",
+                "file_completed",
+                "finished",
+            ]
+        );
+
+        assert!(matches!(
+            &events[0],
+            Event::Planned {
+                files: 1,
+                bundles: 1,
+                transfer: 5
+            }
+        ));
+        assert!(matches!(&events[1], Event::BundleStarted { bundle: name } if name == bundle));
+        assert!(matches!(&events[3], Event::Transferred { bytes: 5 }));
+        assert!(matches!(
+            &events[4],
+            Event::Verified {
+                valid: true,
+                retrying: false,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &events[6],
+            Event::Finished {
+                files: 1,
+                unchanged: 0,
+                bundles: 1
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn plans_leave_the_disk_alone() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("a.txt", b"hello"),
+                TestFile::new("b.txt", b"hello"),
+            ],
+        )]);
+
+        let server = Server::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let store = dir.path().join("store");
+        let manifest = read_manifest(&test.data).unwrap();
+        let downloader = downloader(&server).with_store(Store::open(&store));
+
+        let journal = Journal::open(&output, "1.0").unwrap();
+        let plan = downloader
+            .plan(&manifest, &output, &LocalState::default(), &journal)
+            .unwrap();
+
+        assert_eq!(plan.written, 10);
+        // the chunk is stored once
+        assert_eq!(plan.stored, 5);
+        assert!(plan.check_space(&output, Some(&store)).is_ok());
+        assert!(!output.exists());
+        assert!(!store.exists());
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn repairs_only_fetch_the_broken_files() {
+        let test = manifest(vec![
+            ("valid", vec![TestFile::new("a.txt", b"valid")]),
+            ("broken", vec![TestFile::new("b.txt", b"broken")]),
+            ("grown", vec![TestFile::new("c.txt", b"grown")]),
+        ]);
+
+        let server = Server::start().await;
+        server.route_manifest("1.0", &test);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("a.txt"), b"valid").unwrap();
+        fs::write(output.join("b.txt"), b"brokeN").unwrap();
+        // the chunk of c.txt is still at its offset
+        fs::write(output.join("c.txt"), b"grown and more").unwrap();
+
+        let manifest = read_manifest(&test.data).unwrap();
+        let report = Report::new(&manifest, &output, &[], &Filter::default()).unwrap();
+        assert_eq!(report.corrupted.len(), 2);
+
+        let local = LocalState::from_report(&manifest, &output, &report).unwrap();
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        let summary = downloader(&server)
+            .update(&manifest, &output, &local, &mut journal)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(fs::read(output.join("b.txt")).unwrap(), b"broken");
+        assert_eq!(fs::read(output.join("c.txt")).unwrap(), b"grown");
+        assert!(Report::new(&manifest, &output, &[], &Filter::default())
+            .unwrap()
+            .is_valid());
+
+        let fetched: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.path)
+            .collect();
+        assert_eq!(fetched, [bundle_path(&test.bundles[1].0)]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{fmt::Display, io};
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use crate::game::{Channel, Game, Platform, Target};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum HashMismatch {
+    #[error("chunk at offset {offset} of {file} has hash {actual}, expected {expected}")]
+    Chunk {
+        file: String,
+        offset: u64,
+        expected: String,
+        actual: String,
+    },
+    #[error("{file} has hash {actual}, expected {expected}")]
+    File {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    /// a bundle is named after its hash
+    #[error("bundle {bundle} has hash {actual}")]
+    Bundle { bundle: String, actual: String },
+}
+
+fn list<T: Display>(items: &[T]) -> String {
+    if items.is_empty() {
+        String::from("none")
+    } else {
+        items
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unknown game {game}, cytrus has {}", list(.games))]
+    UnknownGame { game: Game, games: Vec<Game> },
+    #[error("unknown platform {platform}")]
+    UnknownPlatform { platform: String },
+    #[error("{game} has no {platform} build, it has {}", list(.platforms))]
+    MissingPlatform {
+        game: Game,
+        platform: Platform,
+        platforms: Vec<Platform>,
+    },
+    #[error("{game} has no assets meta")]
+    MissingAssets { game: Game },
+    #[error("{game} has no {channel} version for {target}, it has {}", list(.channels))]
+    MissingChannel {
+        game: Game,
+        target: Target,
+        channel: Channel,
+        channels: Vec<Channel>,
+    },
+    #[error("the cdn has no {channel} {target} manifest of {game} {version}")]
+    MissingManifest {
+        game: Game,
+        target: Target,
+        channel: Channel,
+        version: String,
+    },
+    #[error("invalid watch {watch}, expected <game>/<platform or assets>[/<channel>]")]
+    InvalidWatch { watch: String },
+    #[error("{url} answered with {status}")]
+    HttpStatus { url: String, status: StatusCode },
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(#[from] flatbuffers::InvalidFlatbuffer),
+    #[error("invalid manifest: {reason}")]
+    MalformedManifest { reason: String },
+    #[error("file without a name in the manifest")]
+    UnnamedFile,
+    #[error("{file} would be written outside of the install")]
+    UnsafePath { file: String },
+    #[error("{file} has a negative size or offset")]
+    NegativeSize { file: String },
+    #[error("the manifest has no file {file}")]
+    MissingFile { file: String },
+    #[error("chunk {chunk} of {file} is not in any bundle")]
+    MissingChunk { file: String, chunk: String },
+    #[error("bundle {bundle} is shorter than its chunks")]
+    TruncatedBundle { bundle: String },
+    #[error(transparent)]
+    HashMismatch(#[from] HashMismatch),
+    #[error("unknown fragment {fragment}, the manifest has {}", list(.fragments))]
+    UnknownFragment {
+        fragment: String,
+        fragments: Vec<String>,
+    },
+    #[error("symlink {file} points outside of the install: {target}")]
+    UnsafeSymlink { file: String, target: String },
+    #[error("too many levels of symlinks resolving {file}")]
+    SymlinkLoop { file: String },
+    #[error("not enough space for {path}, {needed} bytes are needed but {available} are free")]
+    NoSpace {
+        path: String,
+        needed: u64,
+        available: u64,
+    },
+    #[error(transparent)]
+    Glob(#[from] globset::Error),
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
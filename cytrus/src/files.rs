@@ -0,0 +1,32 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::error::Result;
+
+/// Directory of cytrus in the XDG base directory named by `var`, or in
+/// `$HOME/<fallback>` when `var` isn't set
+pub fn user_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))?;
+
+    Some(base.join("cytrus"))
+}
+
+/// Writes `data` next to `path` and renames it in place, a reader never sees
+/// a partial file
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = path.with_extension(format!("{}.tmp", process::id()));
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)?;
+
+    Ok(())
+}
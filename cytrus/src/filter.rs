@@ -0,0 +1,194 @@
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+use crate::{
+    error::{Error, Result},
+    manifiest_generated::{Fragment, Manifest},
+};
+
+enum Pattern {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Result<Self> {
+        match pattern.strip_prefix("re:") {
+            Some(regex) => Ok(Pattern::Regex(Regex::new(regex)?)),
+            None => Ok(Pattern::Glob(
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()?
+                    .compile_matcher(),
+            )),
+        }
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob.is_match(name),
+            Pattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Include and exclude patterns matched against the name of a file
+///
+/// A pattern is a glob such as `**/GameAssembly.*`, or a regex when it
+/// starts with `re:`. A file is kept when it matches an include pattern, or
+/// when there is none, and doesn't match any exclude pattern.
+#[derive(Default)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Filter {
+            include: include
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_>>()?,
+            exclude: exclude
+                .iter()
+                .map(|pattern| Pattern::new(pattern))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.is_match(name)))
+            && !self.exclude.iter().any(|pattern| pattern.is_match(name))
+    }
+}
+
+/// Fragments of the manifest named in `names`, every fragment when `names` is
+/// empty, a name the manifest doesn't have is an error so that a typo doesn't
+/// select nothing
+pub fn select_fragments<'a>(
+    manifest: &Manifest<'a>,
+    names: &[String],
+) -> Result<Vec<Fragment<'a>>> {
+    let fragments: Vec<Fragment<'a>> = manifest.fragments().unwrap_or_default().iter().collect();
+    let known: Vec<&str> = fragments
+        .iter()
+        .map(|fragment| fragment.name().unwrap_or_default())
+        .collect();
+
+    if let Some(unknown) = names.iter().find(|name| !known.contains(&name.as_str())) {
+        return Err(Error::UnknownFragment {
+            fragment: unknown.clone(),
+            fragments: known.iter().map(|name| name.to_string()).collect(),
+        });
+    }
+
+    Ok(fragments
+        .into_iter()
+        .filter(|fragment| {
+            names.is_empty()
+                || names
+                    .iter()
+                    .any(|name| name == fragment.name().unwrap_or_default())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        read_manifest,
+        testing::{manifest, TestFile},
+    };
+
+    fn filter(include: &[&str], exclude: &[&str]) -> Filter {
+        let strings = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect::<Vec<_>>()
+        };
+        Filter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        assert!(Filter::default().matches("a.txt"));
+        assert!(Filter::default().matches("bin/game"));
+    }
+
+    #[test]
+    fn globs_stop_at_separators() {
+        let top = filter(&["*.txt"], &[]);
+        assert!(top.matches("a.txt"));
+        assert!(!top.matches("dir/a.txt"));
+
+        let nested = filter(&["**/*.txt"], &[]);
+        assert!(nested.matches("a.txt"));
+        assert!(nested.matches("dir/a.txt"));
+        assert!(!nested.matches("dir/a.bin"));
+    }
+
+    #[test]
+    fn regexes_are_prefixed() {
+        let regex = filter(&["re:^bin/.*\\.so$"], &[]);
+        assert!(regex.matches("bin/libgame.so"));
+        assert!(!regex.matches("lib/bin/libgame.so"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = filter(&["**"], &["**/*.log", "re:^tmp/"]);
+        assert!(filter.matches("bin/game"));
+        assert!(!filter.matches("logs/game.log"));
+        assert!(!filter.matches("tmp/file"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert!(Filter::new(&["re:(".to_string()], &[]).is_err());
+        assert!(Filter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn fragments_are_selected_by_name() {
+        let test = manifest(vec![
+            ("main", vec![TestFile::new("a", b"a")]),
+            ("linux", vec![TestFile::new("b", b"b")]),
+        ]);
+        let manifest = read_manifest(&test.data).unwrap();
+        let names = |fragments: Vec<Fragment>| {
+            fragments
+                .iter()
+                .map(|fragment| fragment.name().unwrap_or_default().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(select_fragments(&manifest, &[]).unwrap()),
+            ["main", "linux"]
+        );
+        assert_eq!(
+            names(select_fragments(&manifest, &["linux".to_string()]).unwrap()),
+            ["linux"]
+        );
+    }
+
+    #[test]
+    fn unknown_fragments_are_errors() {
+        let test = manifest(vec![("main", vec![TestFile::new("a", b"a")])]);
+        let manifest = read_manifest(&test.data).unwrap();
+
+        match select_fragments(&manifest, &["mian".to_string()]) {
+            Err(Error::UnknownFragment {
+                fragment,
+                fragments,
+            }) => {
+                assert_eq!(fragment, "mian");
+                assert_eq!(fragments, ["main"]);
+            }
+            _ => panic!("mian was selected"),
+        }
+    }
+}
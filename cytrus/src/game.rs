@@ -0,0 +1,264 @@
+use std::{convert::Infallible, ffi::OsStr, fmt, str::FromStr};
+
+use clap::{
+    builder::{PossibleValue, TypedValueParser, ValueParserFactory},
+    ValueEnum,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// A game of cytrus, games unknown to this release are kept by name
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Game {
+    Dofus,
+    Flyn,
+    Krosfighter,
+    Krosmaga,
+    Onemoregate,
+    Retro,
+    Supernanoblaster,
+    Wakfu,
+    Waven,
+    Other(String),
+}
+
+static GAMES: [Game; 9] = [
+    Game::Dofus,
+    Game::Flyn,
+    Game::Krosfighter,
+    Game::Krosmaga,
+    Game::Onemoregate,
+    Game::Retro,
+    Game::Supernanoblaster,
+    Game::Wakfu,
+    Game::Waven,
+];
+
+impl Game {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Game::Dofus => "dofus",
+            Game::Flyn => "flyn",
+            Game::Krosfighter => "krosfighter",
+            Game::Krosmaga => "krosmaga",
+            Game::Onemoregate => "onemoregate",
+            Game::Retro => "retro",
+            Game::Supernanoblaster => "supernanoblaster",
+            Game::Wakfu => "wakfu",
+            Game::Waven => "waven",
+            Game::Other(name) => name,
+        }
+    }
+}
+
+impl FromStr for Game {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(GAMES
+            .iter()
+            .find(|game| game.as_str() == name)
+            .cloned()
+            .unwrap_or_else(|| Game::Other(name.to_string())))
+    }
+}
+
+/// A release channel, channels unknown to this release are kept by name
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    Main,
+    Beta,
+    Other(String),
+}
+
+static CHANNELS: [Channel; 2] = [Channel::Main, Channel::Beta];
+
+impl Channel {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Channel::Main => "main",
+            Channel::Beta => "beta",
+            Channel::Other(name) => name,
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(CHANNELS
+            .iter()
+            .find(|channel| channel.as_str() == name)
+            .cloned()
+            .unwrap_or_else(|| Channel::Other(name.to_string())))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum)]
+pub enum Platform {
+    Windows,
+    Darwin,
+    Linux,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Windows => "windows",
+            Platform::Darwin => "darwin",
+            Platform::Linux => "linux",
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Platform::value_variants()
+            .iter()
+            .find(|platform| platform.as_str() == name)
+            .copied()
+            .ok_or_else(|| Error::UnknownPlatform {
+                platform: name.to_string(),
+            })
+    }
+}
+
+/// What a manifest is released for, the build of a platform or the assets
+/// meta shared by every platform
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Target {
+    Platform(Platform),
+    Assets,
+}
+
+impl Target {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Target::Platform(platform) => platform.as_str(),
+            Target::Assets => "assets",
+        }
+    }
+}
+
+impl From<Platform> for Target {
+    fn from(platform: Platform) -> Self {
+        Target::Platform(platform)
+    }
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "assets" => Ok(Target::Assets),
+            _ => Ok(Target::Platform(name.parse()?)),
+        }
+    }
+}
+
+/// Displays and serializes a type by its name
+macro_rules! named {
+    ($($name:ident),*) => {$(
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    )*};
+}
+
+named!(Game, Channel, Platform, Target);
+
+/// Implements `ValueEnum` for an enum whose names unknown to `$known` are
+/// kept in a fallback variant
+macro_rules! open_enum {
+    ($($name:ident => $known:ident),*) => {$(
+        impl ValueEnum for $name {
+            fn value_variants<'a>() -> &'a [Self] {
+                &$known
+            }
+
+            fn from_str(input: &str, ignore_case: bool) -> Result<Self, String> {
+                let input = if ignore_case {
+                    input.to_lowercase()
+                } else {
+                    input.to_string()
+                };
+                let Ok(value) = input.parse();
+
+                Ok(value)
+            }
+
+            fn to_possible_value(&self) -> Option<PossibleValue> {
+                $known
+                    .iter()
+                    .find(|known| *known == self)
+                    .map(|known| PossibleValue::new(known.as_str()))
+            }
+        }
+
+        impl ValueParserFactory for $name {
+            type Parser = OpenParser<$name>;
+
+            fn value_parser() -> Self::Parser {
+                OpenParser(std::marker::PhantomData)
+            }
+        }
+    )*};
+}
+
+open_enum!(Game => GAMES, Channel => CHANNELS);
+
+/// Parses any name into `T`, the known variants of `T` are only suggested in
+/// the help
+#[derive(Clone)]
+pub struct OpenParser<T>(std::marker::PhantomData<T>);
+
+impl<T> TypedValueParser for OpenParser<T>
+where
+    T: ValueEnum + FromStr<Err = Infallible> + Clone + Send + Sync + 'static,
+{
+    type Value = T;
+
+    fn parse_ref(
+        &self,
+        _cmd: &clap::Command,
+        _arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        let value = value
+            .to_str()
+            .ok_or_else(|| clap::Error::new(clap::error::ErrorKind::InvalidUtf8))?;
+
+        let Ok(value) = value.parse();
+
+        Ok(value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(
+            T::value_variants()
+                .iter()
+                .filter_map(|variant| variant.to_possible_value()),
+        ))
+    }
+}
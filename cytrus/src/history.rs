@@ -0,0 +1,499 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    api::CytrusResponse,
+    download::hash_bytes,
+    error::Result,
+    files::{user_dir, write_atomic},
+    game::{Channel, Game, Target},
+};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_time(time: u64) -> humantime::Rfc3339Timestamp {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(time))
+}
+
+/// A name used as a path component of the archive, separators are replaced
+/// so a name of cytrus.json can't escape the archive
+fn component(name: &str) -> String {
+    match name {
+        "" | "." | ".." => format!("_{name}"),
+        _ => name.replace(['/', '\\'], "_"),
+    }
+}
+
+/// A version seen in cytrus.json
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VersionEntry {
+    pub game: Game,
+    pub target: Target,
+    pub channel: Channel,
+    pub version: String,
+    /// unix time at which the version was first seen
+    pub seen: u64,
+}
+
+impl fmt::Display for VersionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.game,
+            self.target,
+            self.channel,
+            self.version,
+            format_time(self.seen)
+        )
+    }
+}
+
+/// A manifest kept in the archive
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    pub game: Game,
+    pub target: Target,
+    pub channel: Channel,
+    pub version: String,
+    /// unix time at which the manifest was fetched
+    pub fetched: u64,
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.game,
+            self.target,
+            self.channel,
+            self.version,
+            format_time(self.fetched),
+            self.path.display()
+        )
+    }
+}
+
+/// A cytrus.json kept in the archive, a snapshot is only taken when
+/// cytrus.json changed since the previous one
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    /// unix time at which cytrus.json was fetched
+    pub fetched: u64,
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            format_time(self.fetched),
+            self.hash,
+            self.path.display()
+        )
+    }
+}
+
+/// Entries picked when querying the history, a `None` field matches
+/// everything
+#[derive(Default)]
+pub struct Query {
+    pub game: Option<Game>,
+    pub target: Option<Target>,
+    pub channel: Option<Channel>,
+}
+
+impl Query {
+    fn matches(&self, game: &Game, target: Target, channel: &Channel) -> bool {
+        self.game.as_ref().is_none_or(|wanted| wanted == game)
+            && self.target.is_none_or(|wanted| wanted == target)
+            && self.channel.as_ref().is_none_or(|wanted| wanted == channel)
+    }
+}
+
+/// Archive of the versions, manifests and cytrus.json seen by cytrus
+///
+/// cytrus.json only holds the latest version of each channel and old
+/// manifests are removed from the cdn, the history keeps them for later
+/// downloads and diffs. Each kind of entry is indexed by a json lines file
+/// of `root`, manifests live at
+/// `<root>/manifests/<game>/<target>/<channel>/<version>.manifest` and
+/// snapshots of cytrus.json at `<root>/snapshots/<fetch time>-<hash>.json`.
+/// The directories are only created once something is archived.
+#[derive(Clone)]
+pub struct History {
+    root: PathBuf,
+}
+
+impl History {
+    pub fn open(root: &Path) -> Self {
+        History {
+            root: root.to_path_buf(),
+        }
+    }
+
+    /// `$XDG_DATA_HOME/cytrus/history`, or `~/.local/share/cytrus/history`
+    pub fn default_root() -> Option<PathBuf> {
+        Some(user_dir("XDG_DATA_HOME", ".local/share")?.join("history"))
+    }
+
+    /// Reads the entries of an index, oldest first
+    fn read<T: DeserializeOwned>(&self, index: &str) -> Result<Vec<T>> {
+        let content = match fs::read_to_string(self.root.join(index)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        // a line cut by a killed process is skipped
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    fn append<T: Serialize>(&self, index: &str, entries: &[T]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        fs::create_dir_all(&self.root)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.root.join(index))?
+            .write_all(lines.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Every known version, oldest first
+    pub fn versions(&self, query: &Query) -> Result<Vec<VersionEntry>> {
+        Ok(self
+            .read::<VersionEntry>("versions.jsonl")?
+            .into_iter()
+            .filter(|entry| query.matches(&entry.game, entry.target, &entry.channel))
+            .collect())
+    }
+
+    /// Every archived manifest, oldest first
+    pub fn manifests(&self, query: &Query) -> Result<Vec<ManifestEntry>> {
+        Ok(self
+            .read::<ManifestEntry>("manifests.jsonl")?
+            .into_iter()
+            .filter(|entry| query.matches(&entry.game, entry.target, &entry.channel))
+            .collect())
+    }
+
+    /// Every snapshot of cytrus.json, oldest first
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        self.read("snapshots.jsonl")
+    }
+
+    /// Adds the versions that are not known yet
+    fn add_versions(&self, mut entries: Vec<VersionEntry>) -> Result<()> {
+        let known: HashSet<(Game, Target, Channel, String)> = self
+            .versions(&Query::default())?
+            .into_iter()
+            .map(|entry| (entry.game, entry.target, entry.channel, entry.version))
+            .collect();
+
+        entries.retain(|entry| {
+            !known.contains(&(
+                entry.game.clone(),
+                entry.target,
+                entry.channel.clone(),
+                entry.version.clone(),
+            ))
+        });
+        entries
+            .sort_by(|a, b| (&a.game, a.target, &a.channel).cmp(&(&b.game, b.target, &b.channel)));
+
+        self.append("versions.jsonl", &entries)
+    }
+
+    /// Keeps a snapshot of cytrus.json when it changed and adds the versions
+    /// that are not known yet
+    pub fn record(&self, response: &CytrusResponse, body: &[u8]) -> Result<()> {
+        let fetched = now();
+        let hash = hash_bytes(body);
+
+        if self
+            .snapshots()?
+            .last()
+            .is_none_or(|snapshot| snapshot.hash != hash)
+        {
+            // cytrus.json may change twice within a second
+            let path = self
+                .root
+                .join("snapshots")
+                .join(format!("{fetched}-{hash}.json"));
+            write_atomic(&path, body)?;
+
+            self.append(
+                "snapshots.jsonl",
+                &[Snapshot {
+                    fetched,
+                    hash,
+                    path,
+                }],
+            )?;
+        }
+
+        let mut entries = Vec::new();
+
+        for (game, data) in &response.games {
+            let mut targets: Vec<(Target, _)> = data
+                .platforms
+                .available()
+                .into_iter()
+                .filter_map(|platform| Some((platform.into(), data.platforms.get(platform)?)))
+                .collect();
+
+            if let Some(meta) = data.assets.as_ref().and_then(|assets| assets.meta.as_ref()) {
+                targets.push((Target::Assets, meta));
+            }
+
+            for (target, versions) in targets {
+                for channel in versions.available() {
+                    if let Some(version) = versions.get(&channel) {
+                        entries.push(VersionEntry {
+                            game: game.clone(),
+                            target,
+                            channel,
+                            version: version.clone(),
+                            seen: fetched,
+                        });
+                    }
+                }
+            }
+        }
+
+        self.add_versions(entries)
+    }
+
+    pub fn manifest_path(
+        &self,
+        game: &Game,
+        target: Target,
+        channel: &Channel,
+        version: &str,
+    ) -> PathBuf {
+        self.root
+            .join("manifests")
+            .join(component(game.as_str()))
+            .join(target.as_str())
+            .join(component(channel.as_str()))
+            .join(format!("{}.manifest", component(version)))
+    }
+
+    /// Archives a fetched manifest, a version is only archived once
+    pub fn archive_manifest(
+        &self,
+        game: &Game,
+        target: Target,
+        channel: &Channel,
+        version: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        let path = self.manifest_path(game, target, channel, version);
+
+        if path.is_file() {
+            return Ok(());
+        }
+
+        write_atomic(&path, data)?;
+
+        let fetched = now();
+
+        self.append(
+            "manifests.jsonl",
+            &[ManifestEntry {
+                game: game.clone(),
+                target,
+                channel: channel.clone(),
+                version: version.to_string(),
+                fetched,
+                hash: hash_bytes(data),
+                path,
+            }],
+        )?;
+
+        // a version downloaded with --version may never have been seen in
+        // cytrus.json
+        self.add_versions(vec![VersionEntry {
+            game: game.clone(),
+            target,
+            channel: channel.clone(),
+            version: version.to_string(),
+            seen: fetched,
+        }])
+    }
+
+    /// Reads an archived manifest, `None` when it was never fetched
+    pub fn manifest(
+        &self,
+        game: &Game,
+        target: Target,
+        channel: &Channel,
+        version: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.manifest_path(game, target, channel, version)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Platform;
+
+    fn cytrus(main: &str, beta: &str) -> (CytrusResponse, Vec<u8>) {
+        let body = format!(
+            r#"{{"name":"production","version":6,"games":{{"dofus":{{"name":"Dofus","order":0,"gameId":1,"assets":null,"platforms":{{"windows":{{"main":"{main}","beta":"{beta}"}},"darwin":null,"linux":{{"main":"{main}","beta":null}}}}}}}}}}"#
+        );
+
+        (serde_json::from_str(&body).unwrap(), body.into_bytes())
+    }
+
+    #[test]
+    fn snapshots_are_taken_when_cytrus_json_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path());
+
+        let (response, body) = cytrus("1.0", "1.1");
+        history.record(&response, &body).unwrap();
+        history.record(&response, &body).unwrap();
+
+        assert_eq!(history.snapshots().unwrap().len(), 1);
+        assert_eq!(history.versions(&Query::default()).unwrap().len(), 3);
+
+        let (response, changed) = cytrus("1.0", "1.2");
+        history.record(&response, &changed).unwrap();
+
+        let snapshots = history.snapshots().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        // both snapshots are kept even when taken within the same second
+        assert_ne!(snapshots[0].path, snapshots[1].path);
+        assert_eq!(fs::read(&snapshots[0].path).unwrap(), body);
+        assert_eq!(fs::read(&snapshots[1].path).unwrap(), changed);
+
+        let versions: Vec<String> = history
+            .versions(&Query::default())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.version)
+            .collect();
+        assert_eq!(versions, ["1.0", "1.1", "1.0", "1.2"]);
+    }
+
+    #[test]
+    fn versions_are_queried_by_game_target_and_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path());
+
+        let (response, body) = cytrus("1.0", "1.1");
+        history.record(&response, &body).unwrap();
+
+        let windows = Query {
+            target: Some(Target::Platform(Platform::Windows)),
+            ..Query::default()
+        };
+        assert_eq!(history.versions(&windows).unwrap().len(), 2);
+
+        let beta = Query {
+            game: Some(Game::Dofus),
+            channel: Some(Channel::Beta),
+            ..Query::default()
+        };
+        let versions = history.versions(&beta).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].version, "1.1");
+
+        let assets = Query {
+            target: Some(Target::Assets),
+            ..Query::default()
+        };
+        assert!(history.versions(&assets).unwrap().is_empty());
+    }
+
+    #[test]
+    fn manifests_are_archived_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::open(dir.path());
+        let target = Target::Platform(Platform::Linux);
+
+        assert_eq!(
+            history
+                .manifest(&Game::Dofus, target, &Channel::Main, "1.0")
+                .unwrap(),
+            None
+        );
+
+        for data in [b"first", b"again"] {
+            history
+                .archive_manifest(&Game::Dofus, target, &Channel::Main, "1.0", data)
+                .unwrap();
+        }
+
+        assert_eq!(
+            history
+                .manifest(&Game::Dofus, target, &Channel::Main, "1.0")
+                .unwrap()
+                .unwrap(),
+            b"first"
+        );
+        assert_eq!(history.manifests(&Query::default()).unwrap().len(), 1);
+        // a manifest fetched by version is a known version
+        assert_eq!(history.versions(&Query::default()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn names_stay_inside_the_archive() {
+        assert_eq!(component("1.0"), "1.0");
+        assert_eq!(component("../../etc"), ".._.._etc");
+        assert_eq!(component("a\\b"), "a_b");
+        assert_eq!(component(".."), "_..");
+        assert_eq!(component("."), "_.");
+        assert_eq!(component(""), "_");
+
+        let history = History::open(Path::new("history"));
+        let path = history.manifest_path(
+            &Game::Dofus,
+            Target::Assets,
+            &Channel::Other("..".to_string()),
+            "../../x",
+        );
+        assert_eq!(
+            path,
+            Path::new("history/manifests/dofus/assets/_../.._.._x.manifest")
+        );
+    }
+}
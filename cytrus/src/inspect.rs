@@ -0,0 +1,322 @@
+use std::{cmp::Reverse, collections::BTreeMap, fmt::Write};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    download::{chunk_locations, file_chunks, to_hex},
+    error::Result,
+    filter::{select_fragments, Filter},
+    manifiest_generated::Manifest,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// files as a directory tree
+    Tree,
+    /// a line per file
+    Flat,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Sort {
+    Name,
+    /// biggest first
+    Size,
+}
+
+#[derive(Serialize)]
+pub struct ChunkInfo {
+    pub hash: String,
+    pub offset: i64,
+    pub size: i64,
+    /// bundle holding the chunk of a file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FileInfo {
+    pub name: String,
+    pub size: i64,
+    pub hash: String,
+    pub executable: bool,
+    pub symlink: Option<String>,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Serialize)]
+pub struct BundleInfo {
+    pub hash: String,
+    pub size: i64,
+    pub chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Serialize)]
+pub struct FragmentInfo {
+    pub name: String,
+    pub size: i64,
+    pub files: Vec<FileInfo>,
+    pub bundles: Vec<BundleInfo>,
+}
+
+#[derive(Serialize)]
+pub struct ManifestInfo {
+    pub fragments: Vec<FragmentInfo>,
+}
+
+impl ManifestInfo {
+    /// Reads the fragments of the manifest, only the files matching
+    /// `filter` are kept
+    pub fn new(
+        manifest: &Manifest,
+        fragments: &[String],
+        filter: &Filter,
+        sort: Sort,
+    ) -> Result<Self> {
+        let locations = chunk_locations(manifest);
+
+        let mut infos = Vec::new();
+
+        for fragment in select_fragments(manifest, fragments)? {
+            let name = fragment.name().unwrap_or_default();
+
+            let mut files: Vec<FileInfo> = fragment
+                .files()
+                .unwrap_or_default()
+                .iter()
+                .filter(|file| filter.matches(file.name().unwrap_or_default()))
+                .map(|file| FileInfo {
+                    name: file.name().unwrap_or_default().to_string(),
+                    size: file.size_(),
+                    hash: to_hex(file.hash().unwrap_or_default().bytes()),
+                    executable: file.executable(),
+                    symlink: file
+                        .symlink()
+                        .filter(|symlink| !symlink.is_empty())
+                        .map(String::from),
+                    chunks: if file.size_() > 0 {
+                        file_chunks(&file)
+                            .into_iter()
+                            .map(|chunk| ChunkInfo {
+                                bundle: locations
+                                    .get(&chunk.hash)
+                                    .map(|location| location.bundle.clone()),
+                                hash: chunk.hash,
+                                offset: chunk.offset as i64,
+                                size: chunk.size as i64,
+                            })
+                            .collect()
+                    } else {
+                        Vec::new()
+                    },
+                })
+                .collect();
+
+            match sort {
+                Sort::Name => files.sort_by(|a, b| a.name.cmp(&b.name)),
+                Sort::Size => files.sort_by_key(|file| Reverse(file.size)),
+            }
+
+            let bundles = fragment
+                .bundles()
+                .unwrap_or_default()
+                .iter()
+                .map(|bundle| {
+                    let chunks: Vec<ChunkInfo> = bundle
+                        .chunks()
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|chunk| ChunkInfo {
+                            hash: to_hex(chunk.hash().unwrap_or_default().bytes()),
+                            offset: chunk.offset(),
+                            size: chunk.size_(),
+                            bundle: None,
+                        })
+                        .collect();
+
+                    BundleInfo {
+                        hash: to_hex(bundle.hash().unwrap_or_default().bytes()),
+                        size: chunks
+                            .iter()
+                            .map(|chunk| chunk.offset + chunk.size)
+                            .max()
+                            .unwrap_or_default(),
+                        chunks,
+                    }
+                })
+                .collect();
+
+            infos.push(FragmentInfo {
+                name: name.to_string(),
+                size: files.iter().map(|file| file.size).sum(),
+                files,
+                bundles,
+            });
+        }
+
+        if let Sort::Size = sort {
+            infos.sort_by_key(|fragment| Reverse(fragment.size));
+        }
+
+        Ok(ManifestInfo { fragments: infos })
+    }
+
+    pub fn render(&self, format: Format, chunks: bool) -> String {
+        match format {
+            Format::Tree => self.tree(chunks),
+            Format::Flat => self.flat(chunks),
+            Format::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+        }
+    }
+
+    fn flat(&self, chunks: bool) -> String {
+        let mut out = String::new();
+
+        for fragment in &self.fragments {
+            for file in &fragment.files {
+                let _ = writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}{}",
+                    fragment.name,
+                    file.size,
+                    file.hash,
+                    file.name,
+                    flags(file)
+                );
+
+                if chunks {
+                    write_chunks(&mut out, "\t", &file.chunks);
+                }
+            }
+
+            if chunks {
+                write_bundles(&mut out, "", fragment);
+            }
+        }
+
+        out
+    }
+
+    fn tree(&self, chunks: bool) -> String {
+        let mut out = String::new();
+
+        for fragment in &self.fragments {
+            let _ = writeln!(
+                out,
+                "{} ({} files, {} bytes)",
+                fragment.name,
+                fragment.files.len(),
+                fragment.size
+            );
+
+            let mut root = Node::default();
+            for file in &fragment.files {
+                root.insert(&file.name, file);
+            }
+            root.write(&mut out, "", chunks);
+
+            if chunks {
+                write_bundles(&mut out, "  ", fragment);
+            }
+        }
+
+        out
+    }
+}
+
+/// Flags of the file and the target of a symlink
+fn flags(file: &FileInfo) -> String {
+    let mut flags = String::new();
+
+    if file.executable {
+        flags.push_str(" [x]");
+    }
+    if let Some(symlink) = &file.symlink {
+        let _ = write!(flags, " -> {symlink}");
+    }
+
+    flags
+}
+
+fn write_chunks(out: &mut String, indent: &str, chunks: &[ChunkInfo]) {
+    for chunk in chunks {
+        let _ = write!(
+            out,
+            "{indent}{}+{} {}",
+            chunk.offset, chunk.size, chunk.hash
+        );
+        if let Some(bundle) = &chunk.bundle {
+            let _ = write!(out, " in {bundle}");
+        }
+        out.push('\n');
+    }
+}
+
+fn write_bundles(out: &mut String, indent: &str, fragment: &FragmentInfo) {
+    for bundle in &fragment.bundles {
+        let _ = writeln!(
+            out,
+            "{indent}bundle {} ({} chunks, {} bytes)",
+            bundle.hash,
+            bundle.chunks.len(),
+            bundle.size
+        );
+        write_chunks(out, &format!("{indent}\t"), &bundle.chunks);
+    }
+}
+
+/// A directory of the tree, files keep the order they were inserted in
+#[derive(Default)]
+struct Node<'a> {
+    directories: BTreeMap<&'a str, Node<'a>>,
+    files: Vec<&'a FileInfo>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, path: &'a str, file: &'a FileInfo) {
+        match path.split_once('/') {
+            Some((directory, rest)) => self
+                .directories
+                .entry(directory)
+                .or_default()
+                .insert(rest, file),
+            None => self.files.push(file),
+        }
+    }
+
+    fn write(&self, out: &mut String, indent: &str, chunks: bool) {
+        let count = self.directories.len() + self.files.len();
+
+        for (index, (name, directory)) in self.directories.iter().enumerate() {
+            let last = index + 1 == count;
+
+            let _ = writeln!(out, "{indent}{}{name}/", if last { "└── " } else { "├── " });
+            directory.write(
+                out,
+                &format!("{indent}{}", if last { "    " } else { "│   " }),
+                chunks,
+            );
+        }
+
+        for (index, file) in self.files.iter().enumerate() {
+            let last = self.directories.len() + index + 1 == count;
+            let name = file.name.rsplit('/').next().unwrap_or_default();
+
+            let _ = writeln!(
+                out,
+                "{indent}{}{name} ({} bytes, {}){}",
+                if last { "└── " } else { "├── " },
+                file.size,
+                file.hash,
+                flags(file)
+            );
+
+            if chunks {
+                let indent = format!("{indent}{}    ", if last { "    " } else { "│   " });
+                write_chunks(out, &indent, &file.chunks);
+            }
+        }
+    }
+}
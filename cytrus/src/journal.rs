@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// A line of the journal
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Entry {
+    Version(String),
+    Chunk {
+        file: String,
+        offset: u64,
+        hash: String,
+    },
+    File {
+        name: String,
+        hash: String,
+    },
+}
+
+fn write_entry(file: &mut fs::File, entry: &Entry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+
+    Ok(())
+}
+
+/// Progress of a download, kept next to the output directory so a killed
+/// download can pick up where it left off
+///
+/// The journal is a json line per completed chunk or file, the first line
+/// holds the version of the manifest being downloaded. The file is only
+/// written once the first entry is added.
+pub struct Journal {
+    path: PathBuf,
+    version: String,
+    /// the journal on disk was written for this version
+    valid: bool,
+    file: Option<fs::File>,
+    files: HashMap<String, String>,
+    /// hash of the chunks written to the .part of a file, by offset
+    chunks: HashMap<String, HashMap<u64, String>>,
+}
+
+impl Journal {
+    pub fn path(output: &Path) -> PathBuf {
+        let mut name = output.file_name().unwrap_or_default().to_os_string();
+        name.push(".journal");
+        output.with_file_name(name)
+    }
+
+    /// Opens the journal of `output`, its entries are dropped when it was
+    /// written for another version
+    pub fn open(output: &Path, version: &str) -> Result<Self> {
+        let path = Journal::path(output);
+
+        let mut files = HashMap::new();
+        let mut chunks: HashMap<String, HashMap<u64, String>> = HashMap::new();
+        let mut valid = false;
+
+        if let Ok(content) = fs::read_to_string(&path) {
+            // a line cut by a killed process is ignored
+            let mut entries = content
+                .lines()
+                .map_while(|line| serde_json::from_str::<Entry>(line).ok());
+
+            if let Some(Entry::Version(journal_version)) = entries.next() {
+                valid = journal_version == version;
+            }
+
+            for entry in entries.filter(|_| valid) {
+                match entry {
+                    Entry::Chunk { file, offset, hash } => {
+                        chunks.entry(file).or_default().insert(offset, hash);
+                    }
+                    Entry::File { name, hash } => {
+                        files.insert(name, hash);
+                    }
+                    Entry::Version(_) => {}
+                }
+            }
+        }
+
+        Ok(Journal {
+            path,
+            version: version.to_string(),
+            valid,
+            file: None,
+            files,
+            chunks,
+        })
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .truncate(false)
+                    .open(&self.path)?;
+
+                if !self.valid {
+                    file.set_len(0)?;
+                    write_entry(&mut file, &Entry::Version(self.version.clone()))?;
+                }
+
+                self.file.insert(file)
+            }
+        };
+
+        write_entry(file, entry)
+    }
+
+    pub fn has_file(&self, name: &str, hash: &str) -> bool {
+        self.files.get(name).is_some_and(|journal| journal == hash)
+    }
+
+    pub fn has_chunks(&self, file: &str) -> bool {
+        self.chunks.contains_key(file)
+    }
+
+    pub fn has_chunk(&self, file: &str, offset: u64, hash: &str) -> bool {
+        self.chunks
+            .get(file)
+            .and_then(|chunks| chunks.get(&offset))
+            .is_some_and(|journal| journal == hash)
+    }
+
+    pub fn add_chunk(&mut self, file: &str, offset: u64, hash: &str) -> Result<()> {
+        self.append(&Entry::Chunk {
+            file: file.to_string(),
+            offset,
+            hash: hash.to_string(),
+        })?;
+        self.chunks
+            .entry(file.to_string())
+            .or_default()
+            .insert(offset, hash.to_string());
+
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: &str, hash: &str) -> Result<()> {
+        self.append(&Entry::File {
+            name: name.to_string(),
+            hash: hash.to_string(),
+        })?;
+        self.files.insert(name.to_string(), hash.to_string());
+
+        Ok(())
+    }
+
+    /// Removes the journal once the download is complete
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+
+        match fs::remove_file(self.path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("dofus");
+        (dir, output)
+    }
+
+    #[test]
+    fn entries_are_kept_for_the_same_version() {
+        let (_dir, output) = output();
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_chunk("a", 0, "aa").unwrap();
+        journal.add_file("b", "bb").unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&output, "1.0").unwrap();
+        assert!(journal.has_chunks("a"));
+        assert!(journal.has_chunk("a", 0, "aa"));
+        assert!(!journal.has_chunk("a", 0, "ab"));
+        assert!(journal.has_file("b", "bb"));
+        assert!(!journal.has_file("b", "bc"));
+    }
+
+    #[test]
+    fn a_cut_off_line_is_ignored() {
+        let (_dir, output) = output();
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_file("a", "aa").unwrap();
+        drop(journal);
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(Journal::path(&output))
+            .unwrap();
+        file.write_all(br#"{"file":{"name":"b","ha"#).unwrap();
+
+        let journal = Journal::open(&output, "1.0").unwrap();
+        assert!(journal.has_file("a", "aa"));
+        assert!(!journal.has_file("b", "bb"));
+    }
+
+    #[test]
+    fn entries_of_another_version_are_dropped() {
+        let (_dir, output) = output();
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_file("a", "aa").unwrap();
+        drop(journal);
+
+        let mut journal = Journal::open(&output, "2.0").unwrap();
+        assert!(!journal.has_file("a", "aa"));
+
+        // the journal is rewritten for the new version on the first entry
+        journal.add_file("b", "bb").unwrap();
+        drop(journal);
+
+        let content = fs::read_to_string(Journal::path(&output)).unwrap();
+        assert!(content.starts_with(r#"{"version":"2.0"}"#));
+
+        let journal = Journal::open(&output, "2.0").unwrap();
+        assert!(!journal.has_file("a", "aa"));
+        assert!(journal.has_file("b", "bb"));
+    }
+
+    #[test]
+    fn finish_removes_the_journal() {
+        let (_dir, output) = output();
+
+        Journal::open(&output, "1.0").unwrap().finish().unwrap();
+        assert!(!Journal::path(&output).exists());
+
+        let mut journal = Journal::open(&output, "1.0").unwrap();
+        journal.add_file("a", "aa").unwrap();
+        assert!(Journal::path(&output).exists());
+
+        journal.finish().unwrap();
+        assert!(!Journal::path(&output).exists());
+    }
+}
@@ -0,0 +1,158 @@
+//! Client of the cytrus cdn Ankama games are distributed through
+//!
+//! [`Api`] fetches the versions of the games and their manifests,
+//! [`Downloader`] rebuilds the files of a manifest from its bundles.
+
+// import the flatbuffers runtime library
+extern crate flatbuffers;
+
+pub mod api;
+pub mod diff;
+pub mod download;
+pub mod error;
+mod files;
+pub mod filter;
+pub mod game;
+pub mod history;
+pub mod inspect;
+pub mod journal;
+pub mod list;
+// import the generated code
+#[allow(dead_code, unused_imports, clippy::missing_safety_doc)]
+#[path = "./manifiest_generated.rs"]
+pub mod manifiest_generated;
+pub mod progress;
+pub mod store;
+#[cfg(test)]
+mod testing;
+pub mod verify;
+pub mod watch;
+
+pub use api::Api;
+pub use download::Downloader;
+pub use error::{Error, Result};
+pub use game::{Channel, Game, Platform, Target};
+pub use manifiest_generated::Manifest;
+
+use manifiest_generated::Chunk;
+
+/// Reads a manifest fetched with [`Api::get_manifiest`] or stored on disk
+pub fn read_manifest(data: &[u8]) -> Result<Manifest<'_>> {
+    let manifest = flatbuffers::root::<Manifest>(data)?;
+    check_manifest(&manifest)?;
+
+    Ok(manifest)
+}
+
+/// Bytes of a SHA-1, 40 hex characters once encoded
+const HASH_SIZE: usize = 20;
+
+fn check_hash(
+    hash: Option<flatbuffers::Vector<'_, i8>>,
+    what: impl FnOnce() -> String,
+) -> Result<()> {
+    if hash.is_some_and(|hash| hash.len() == HASH_SIZE) {
+        return Ok(());
+    }
+
+    Err(Error::MalformedManifest {
+        reason: format!("{} has no valid hash", what()),
+    })
+}
+
+/// Fails when a chunk has a negative offset or size, or ends past the
+/// largest offset so its end can't be computed
+fn check_range(chunk: &Chunk, what: impl FnOnce() -> String) -> Result<()> {
+    let reason = if chunk.offset() < 0 || chunk.size_() < 0 {
+        "has a negative offset or size"
+    } else if chunk.offset().checked_add(chunk.size_()).is_none() {
+        "ends past the largest offset"
+    } else {
+        return Ok(());
+    };
+
+    Err(Error::MalformedManifest {
+        reason: format!("{} {reason}", what()),
+    })
+}
+
+/// Fails unless every bundle, chunk and file has a hash, a symlink may have
+/// none, and every chunk has a valid offset and size
+fn check_manifest(manifest: &Manifest) -> Result<()> {
+    for fragment in manifest.fragments().unwrap_or_default() {
+        for bundle in fragment.bundles().unwrap_or_default() {
+            check_hash(bundle.hash(), || "a bundle".to_string())?;
+            let name = download::to_hex(bundle.hash().unwrap_or_default().bytes());
+
+            for chunk in bundle.chunks().unwrap_or_default() {
+                check_hash(chunk.hash(), || format!("a chunk of bundle {name}"))?;
+                check_range(&chunk, || format!("a chunk of bundle {name}"))?;
+            }
+        }
+
+        for file in fragment.files().unwrap_or_default() {
+            let name = file.name().unwrap_or_default();
+
+            if file.symlink().is_none_or(|target| target.is_empty()) {
+                check_hash(file.hash(), || name.to_string())?;
+            }
+
+            for chunk in file.chunks().unwrap_or_default() {
+                check_hash(chunk.hash(), || format!("a chunk of {name}"))?;
+                check_range(&chunk, || format!("a chunk of {name}"))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{manifest, TestFile};
+
+    #[test]
+    fn manifests_with_hashes_are_read() {
+        let test = manifest(vec![(
+            "main",
+            vec![TestFile::new("a", b"a"), TestFile::symlink("b", "a")],
+        )]);
+
+        assert!(read_manifest(&test.data).is_ok());
+    }
+
+    #[test]
+    fn manifests_without_hashes_are_refused() {
+        let test = manifest(vec![(
+            "main",
+            vec![TestFile {
+                hashed: false,
+                ..TestFile::new("a", b"a")
+            }],
+        )]);
+
+        assert!(matches!(
+            read_manifest(&test.data),
+            Err(Error::MalformedManifest { .. })
+        ));
+    }
+
+    #[test]
+    fn chunks_ending_past_the_largest_offset_are_refused() {
+        let test = manifest(vec![(
+            "main",
+            vec![TestFile {
+                offset: i64::MAX,
+                ..TestFile::new("a", b"a")
+            }],
+        )]);
+
+        match read_manifest(&test.data) {
+            Err(Error::MalformedManifest { reason }) => {
+                assert_eq!(reason, "a chunk of a ends past the largest offset");
+            }
+            _ => panic!("the chunk was accepted"),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    api::{CytrusResponse, VersionResponse},
+    game::{Game, Platform},
+};
+
+#[derive(Serialize)]
+pub struct Versions {
+    pub main: Option<String>,
+    pub beta: Option<String>,
+    /// channels other than main and beta
+    #[serde(flatten)]
+    pub others: BTreeMap<String, Option<String>>,
+}
+
+impl From<&VersionResponse> for Versions {
+    fn from(response: &VersionResponse) -> Self {
+        Versions {
+            main: response.main.clone(),
+            beta: response.beta.clone(),
+            others: response
+                .others
+                .iter()
+                .map(|(channel, version)| (channel.clone(), version.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PlatformInfo {
+    pub platform: Platform,
+    #[serde(flatten)]
+    pub versions: Versions,
+}
+
+#[derive(Serialize)]
+pub struct GameInfo {
+    pub game: Game,
+    pub id: u8,
+    pub name: String,
+    pub order: u8,
+    /// platforms the game is released on
+    pub platforms: Vec<PlatformInfo>,
+    /// versions of the assets meta
+    pub assets: Option<Versions>,
+}
+
+#[derive(Serialize)]
+pub struct GameList {
+    pub games: Vec<GameInfo>,
+}
+
+impl GameList {
+    /// Every game of cytrus.json, in the order of the launcher
+    pub fn new(response: &CytrusResponse) -> Self {
+        let mut games: Vec<GameInfo> = response
+            .games
+            .iter()
+            .map(|(game, data)| GameInfo {
+                game: game.clone(),
+                id: data.game_id,
+                name: data.name.clone(),
+                order: data.order,
+                platforms: data
+                    .platforms
+                    .available()
+                    .into_iter()
+                    .filter_map(|platform| {
+                        Some(PlatformInfo {
+                            platform,
+                            versions: data.platforms.get(platform)?.into(),
+                        })
+                    })
+                    .collect(),
+                assets: data
+                    .assets
+                    .as_ref()
+                    .and_then(|assets| assets.meta.as_ref())
+                    .map(Versions::from),
+            })
+            .collect();
+
+        games.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.game.cmp(&b.game)));
+
+        GameList { games }
+    }
+}
+
+fn cell(version: Option<&str>) -> String {
+    version.unwrap_or("-").to_string()
+}
+
+impl fmt::Display for GameList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![[
+            String::from("GAME"),
+            String::from("NAME"),
+            String::from("PLATFORM"),
+            String::from("MAIN"),
+            String::from("BETA"),
+            String::from("ASSETS"),
+        ]];
+
+        for game in &self.games {
+            let assets = cell(
+                game.assets
+                    .as_ref()
+                    .and_then(|assets| assets.main.as_deref()),
+            );
+
+            if game.platforms.is_empty() {
+                rows.push([
+                    game.game.to_string(),
+                    game.name.clone(),
+                    String::from("-"),
+                    String::from("-"),
+                    String::from("-"),
+                    assets.clone(),
+                ]);
+            }
+
+            for platform in &game.platforms {
+                rows.push([
+                    game.game.to_string(),
+                    game.name.clone(),
+                    platform.platform.to_string(),
+                    cell(platform.versions.main.as_deref()),
+                    cell(platform.versions.beta.as_deref()),
+                    assets.clone(),
+                ]);
+            }
+        }
+
+        let mut widths = [0; 6];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for (index, row) in rows.iter().enumerate() {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,109 +1,896 @@
-// import the flatbuffers runtime library
-extern crate flatbuffers;
-
-// import the generated code
-mod api;
-#[allow(dead_code, unused_imports)]
-#[path = "./manifiest_generated.rs"]
-mod manifiest_generated;
-
-use anyhow::{Ok, Result};
-use api::Api;
-use clap::{builder::PossibleValuesParser, Parser, Subcommand};
-use manifiest_generated::Manifest;
-
-const GAMES: [&str; 9] = [
-    "dofus",
-    "flyn",
-    "krosfighter",
-    "krosmaga",
-    "onemoregate",
-    "retro",
-    "supernanoblaster",
-    "wakfu",
-    "waven",
-];
-
-const PLATFORMS: [&str; 3] = ["windows", "darwin", "linux"];
+use std::{
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use anyhow::{bail, Ok, Result};
+use bytes::Bytes;
+use clap::{
+    builder::{Resettable, ValueParserFactory},
+    Args, CommandFactory, Parser, Subcommand, ValueEnum,
+};
+use cytrus::{
+    api::{RetryPolicy, CDN_URL},
+    diff::ManifestDiff,
+    download::{hash_bytes, LocalState},
+    filter::Filter,
+    history::{History, Query},
+    inspect::{Format, ManifestInfo, Sort},
+    journal::Journal,
+    list::GameList,
+    progress::{JsonReporter, TerminalReporter},
+    read_manifest,
+    store::Store,
+    verify::Report,
+    watch::{self, Event, Watcher},
+    Api, Channel, Downloader, Game, Platform, Target,
+};
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
 
 #[derive(Parser)]
 #[command(name = "cytrus")]
 struct Cli {
+    /// address of the cytrus cdn or of a mirror of it
+    #[arg(long, env = "CYTRUS_URL", default_value = CDN_URL, global = true)]
+    url: String,
+    /// directory of the chunk store shared by every download, defaults to
+    /// the cache directory of the user
+    #[arg(long, env = "CYTRUS_STORE", global = true)]
+    store: Option<PathBuf>,
+    /// don't read or write chunks from the chunk store
+    #[arg(long, global = true)]
+    no_store: bool,
+    /// directory of the archive of the versions, manifests and cytrus.json
+    /// seen, defaults to the data directory of the user
+    #[arg(long, env = "CYTRUS_HISTORY", global = true)]
+    history: Option<PathBuf>,
+    /// don't read or write the archive of the history
+    #[arg(long, global = true)]
+    no_history: bool,
+    /// times a request failing with a timeout, a connection error, a 429 or
+    /// a 5xx is retried, with an exponential backoff
+    #[arg(long, default_value_t = RetryPolicy::default().retries, global = true)]
+    retries: u32,
+    /// time allowed to connect to the cdn
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration, global = true)]
+    connect_timeout: Duration,
+    /// time without receiving data after which a request fails
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration, global = true)]
+    read_timeout: Duration,
+    /// how the progress of downloads is reported on stderr
+    #[arg(long, value_enum, default_value_t = Progress::Bar, global = true)]
+    progress: Progress,
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+#[derive(ValueEnum, Clone, Copy)]
+enum Progress {
+    /// a progress bar, hidden when stderr isn't a terminal
+    Bar,
+    /// one json event per line, for scripts and CI
+    Json,
+    None,
+}
+
+/// Game, platform and channel of a release
+#[derive(Args)]
+struct Release {
+    /// game of the release, any game of cytrus.json is accepted
+    #[arg(short, long, value_parser = Game::value_parser())]
+    game: Game,
+    /// platform of the game
+    #[arg(short, long, value_enum, required_unless_present = "assets")]
+    platform: Option<Platform>,
+    /// get the assets meta of the game instead of the build of a platform
+    #[arg(long, conflicts_with = "platform")]
+    assets: bool,
+    /// release channel, any channel of cytrus.json is accepted
+    #[arg(long, value_parser = Channel::value_parser(), default_value_t = Channel::Main)]
+    channel: Channel,
+    /// get the beta version, same as `--channel beta`
+    #[arg(short, long, conflicts_with = "channel")]
+    beta: bool,
+}
+
+impl Release {
+    fn target(&self) -> Target {
+        match self.platform {
+            Some(platform) => Target::Platform(platform),
+            None => Target::Assets,
+        }
+    }
+
+    fn channel(&self) -> Channel {
+        if self.beta {
+            Channel::Beta
+        } else {
+            self.channel.clone()
+        }
+    }
+
+    async fn latest_version(&self, api: &Api) -> Result<String> {
+        Ok(api
+            .get_latest_version(&self.game, self.target(), &self.channel())
+            .await?)
+    }
+
+    async fn manifest(&self, api: &Api, version: &str) -> Result<Bytes> {
+        Ok(api
+            .get_manifiest(&self.game, self.target(), version, &self.channel())
+            .await?)
+    }
+
+    /// Directory of the install, the game name or `<game>-assets` for the
+    /// assets meta
+    fn output(&self) -> PathBuf {
+        match self.target() {
+            Target::Platform(_) => PathBuf::from(self.game.as_str()),
+            Target::Assets => PathBuf::from(format!("{}-assets", self.game)),
+        }
+    }
+}
+
+/// Fragments and files a command works on
+#[derive(Args)]
+struct Selection {
+    /// only the files of this fragment, can be repeated
+    #[arg(short, long = "fragment")]
+    fragments: Vec<String>,
+    /// only the files matching this glob, or this regex when prefixed with
+    /// `re:`, can be repeated
+    #[arg(short, long)]
+    include: Vec<String>,
+    /// skip the files matching this glob, or this regex when prefixed with
+    /// `re:`, can be repeated
+    #[arg(short, long)]
+    exclude: Vec<String>,
+}
+
+impl Selection {
+    fn filter(&self) -> Result<Filter> {
+        Ok(Filter::new(&self.include, &self.exclude)?)
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// download the game
     Download {
-        /// game to download
-        #[arg(short, long, value_parser(PossibleValuesParser::new(GAMES)))]
-        game: String,
-        /// platform of the game
-        #[arg(short, long, value_parser(PossibleValuesParser::new(PLATFORMS)))]
-        platform: String,
-        /// get the beta version or not
+        #[command(flatten)]
+        release: Release,
+        /// version to download, defaults to the latest version, the known
+        /// versions are listed by `cytrus history`
+        #[arg(long)]
+        version: Option<String>,
+        /// directory where the game is written, defaults to the game name, or
+        /// `<game>-assets` for the assets meta
         #[arg(short, long)]
-        beta: bool,
+        output: Option<PathBuf>,
+        /// number of bundles fetched at the same time
+        #[arg(short, long, default_value_t = 16)]
+        concurrency: usize,
+        #[command(flatten)]
+        selection: Selection,
+        /// list the fragments of the game without downloading it
+        #[arg(long)]
+        list_fragments: bool,
+        /// print what would be fetched and written without downloading
+        /// anything, fails when the files don't fit on the disk
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// write a single file of the game to stdout, only the chunks of the
+    /// file are fetched and nothing else is written to disk
+    Cat {
+        #[command(flatten)]
+        release: Release,
+        /// version to read the file from, defaults to the latest version
+        #[arg(long)]
+        version: Option<String>,
+        /// path of the file in the manifest, as printed by `cytrus inspect`
+        file: String,
+        /// write the file to this path instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// number of chunks fetched at the same time
+        #[arg(short, long, default_value_t = 16)]
+        concurrency: usize,
+    },
+    /// update an installed game, only the changed chunks are fetched
+    Update {
+        #[command(flatten)]
+        release: Release,
+        /// version to update to, defaults to the latest version
+        #[arg(long)]
+        version: Option<String>,
+        /// directory of the install, defaults to the game name, or
+        /// `<game>-assets` for the assets meta
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// number of bundles fetched at the same time
+        #[arg(short, long, default_value_t = 16)]
+        concurrency: usize,
+        /// manifest of the installed version, the files of the install are
+        /// hashed when it isn't given
+        #[arg(long)]
+        previous: Option<PathBuf>,
+    },
+    /// compare the files of two versions of a game
+    #[command(
+        mut_arg("game", |arg| arg.required(false)),
+        mut_arg("platform", |arg| arg.required_unless_present(Resettable::Reset))
+    )]
+    Diff {
+        /// only needed to fetch a manifest
+        #[command(flatten)]
+        release: Option<Release>,
+        /// old version or path to its .manifest file
+        from: String,
+        /// new version or path to its .manifest file
+        to: String,
+        /// print the differences as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// print the fragments, files and bundles of a manifest
+    #[command(
+        mut_arg("game", |arg| arg.required(false)),
+        mut_arg("platform", |arg| arg.required_unless_present(Resettable::Reset))
+    )]
+    Inspect {
+        /// only needed to fetch a manifest
+        #[command(flatten)]
+        release: Option<Release>,
+        /// version or path to a .manifest file, defaults to the latest version
+        source: Option<String>,
+        #[arg(long, value_enum, default_value_t = Format::Tree)]
+        format: Format,
+        #[arg(long, value_enum, default_value_t = Sort::Name)]
+        sort: Sort,
+        /// also print the chunks of the files and the bundles holding them
+        #[arg(long)]
+        chunks: bool,
+        #[command(flatten)]
+        selection: Selection,
+    },
+    /// hash the files of an install and report the missing, corrupted and
+    /// extra ones, exits with 1 when a file is missing or corrupted
+    Verify {
+        #[command(flatten)]
+        release: Release,
+        /// version or path to a .manifest file, defaults to the latest version
+        source: Option<String>,
+        /// directory of the install, defaults to the game name, or
+        /// `<game>-assets` for the assets meta
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        selection: Selection,
+        /// print the report as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// verify an install and fetch only the chunks of its missing and
+    /// corrupted files
+    Repair {
+        #[command(flatten)]
+        release: Release,
+        /// version or path to a .manifest file, defaults to the latest version
+        source: Option<String>,
+        /// directory of the install, defaults to the game name, or
+        /// `<game>-assets` for the assets meta
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// number of bundles fetched at the same time
+        #[arg(short, long, default_value_t = 16)]
+        concurrency: usize,
+        #[command(flatten)]
+        selection: Selection,
+    },
+    /// list the versions, manifests or cytrus.json snapshots of the history,
+    /// oldest first
+    History {
+        /// only list the versions of this game
+        #[arg(short, long, value_parser = Game::value_parser())]
+        game: Option<Game>,
+        /// only list the versions of this platform
+        #[arg(short, long, value_enum)]
+        platform: Option<Platform>,
+        /// only list the versions of the assets meta
+        #[arg(long, conflicts_with = "platform")]
+        assets: bool,
+        /// only list the versions of this channel
+        #[arg(long, value_parser = Channel::value_parser())]
+        channel: Option<Channel>,
+        /// list the archived manifests instead of the versions
+        #[arg(long)]
+        manifests: bool,
+        /// list the snapshots of cytrus.json instead of the versions
+        #[arg(long, conflicts_with = "manifests")]
+        snapshots: bool,
+        /// print the entries as json
+        #[arg(long)]
+        json: bool,
+    },
+    /// poll cytrus.json and run a command or write an event when the version
+    /// of a watched channel changes
+    Watch {
+        /// channel to watch, written `<game>/<platform or assets>[/<channel>]`
+        /// with main as the default channel, can be repeated
+        #[arg(short, long = "watch", required = true)]
+        watches: Vec<watch::Watch>,
+        /// time between two polls, such as `30s` or `5m`
+        #[arg(long, default_value = "5m", value_parser = humantime::parse_duration)]
+        interval: Duration,
+        /// shell command run on each change, the old and new versions are its
+        /// arguments and CYTRUS_GAME, CYTRUS_TARGET, CYTRUS_CHANNEL,
+        /// CYTRUS_OLD_VERSION and CYTRUS_NEW_VERSION are set
+        #[arg(long)]
+        exec: Option<String>,
+        /// file each change is appended to as a json line
+        #[arg(long)]
+        events: Option<PathBuf>,
+    },
+    /// list every game of cytrus.json with the versions of its platforms
+    List {
+        /// print the games as json
+        #[arg(long)]
+        json: bool,
     },
     /// get the latest version for a given game
     Version {
-        /// game to download
-        #[arg(short, long, value_parser(PossibleValuesParser::new(GAMES)))]
-        game: String,
-        /// platform of the game
-        #[arg(short, long, value_parser(PossibleValuesParser::new(PLATFORMS)))]
-        platform: String,
-        /// get the beta version or not
-        #[arg(short, long)]
-        beta: bool,
+        #[command(flatten)]
+        release: Release,
     },
 }
 
+/// Release of a command that only needs one to fetch `what`
+fn fetched_release<'a>(release: Option<&'a Release>, what: &str) -> Result<&'a Release> {
+    match release {
+        Some(release) if release.platform.is_some() || release.assets => Ok(release),
+        Some(_) => bail!("--platform or --assets is needed to fetch {what}"),
+        None => bail!("--game and --platform or --assets are needed to fetch {what}"),
+    }
+}
+
+/// Reads a .manifest file when `source` is a path, fetches the manifest of
+/// the `source` version of `release` otherwise
+async fn load_manifest(api: &Api, release: Option<&Release>, source: &str) -> Result<Bytes> {
+    if Path::new(source).is_file() {
+        return Ok(fs::read(source)?.into());
+    }
+
+    fetched_release(release, source)?
+        .manifest(api, source)
+        .await
+}
+
+/// Opens the history picked on the command line
+fn open_history(history: Option<PathBuf>, no_history: bool) -> Option<History> {
+    if no_history {
+        return None;
+    }
+
+    Some(History::open(&history.or_else(History::default_root)?))
+}
+
+/// Entries of the history as json or as a line each
+fn render_entries<T: Serialize + Display>(entries: &[T], json: bool) -> Result<String> {
+    if json {
+        return Ok(serde_json::to_string_pretty(entries)?);
+    }
+
+    Ok(entries
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Appends an event to the event file as a json line
+fn write_event(path: &Path, event: &Event) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(event)?)?;
+
+    Ok(())
+}
+
+/// Runs the command of `cytrus watch` for an event, a failing command is
+/// reported without stopping the watch
+async fn run_hook(command: &str, event: &Event) -> Result<()> {
+    let old = event.old.as_deref().unwrap_or_default();
+    let new = event.new.as_deref().unwrap_or_default();
+
+    #[cfg(unix)]
+    let mut hook = tokio::process::Command::new("sh");
+    #[cfg(unix)]
+    hook.arg("-c").arg(command).arg("cytrus").arg(old).arg(new);
+    #[cfg(not(unix))]
+    let mut hook = tokio::process::Command::new("cmd");
+    #[cfg(not(unix))]
+    hook.arg("/C").arg(command).arg(old).arg(new);
+
+    let status = hook
+        .env("CYTRUS_GAME", event.game.as_str())
+        .env("CYTRUS_TARGET", event.target.as_str())
+        .env("CYTRUS_CHANNEL", event.channel.as_str())
+        .env("CYTRUS_OLD_VERSION", old)
+        .env("CYTRUS_NEW_VERSION", new)
+        .status()
+        .await?;
+
+    if !status.success() {
+        eprintln!("{command} exited with {status}");
+    }
+
+    Ok(())
+}
+
+/// Adds the chunk store picked on the command line to a downloader
+fn with_store(downloader: Downloader, store: Option<PathBuf>, no_store: bool) -> Downloader {
+    if no_store {
+        return downloader;
+    }
+
+    match store.or_else(Store::default_root) {
+        Some(root) => downloader.with_store(Store::open(&root)),
+        None => downloader,
+    }
+}
+
+/// Adds the progress reporter picked on the command line to a downloader
+fn with_progress(downloader: Downloader, progress: Progress) -> Downloader {
+    match progress {
+        Progress::Bar => downloader.with_reporter(TerminalReporter::new()),
+        Progress::Json => downloader.with_reporter(JsonReporter::new(io::stderr())),
+        Progress::None => downloader,
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Cli::parse();
-    let result = match args.command.unwrap() {
-        Commands::Version {
-            game,
-            platform,
-            beta,
-        } => Api::get_latest_version(&game, &platform, &beta).await?,
-        Commands::Download {
+    let history = open_history(args.history, args.no_history);
+    // only the commands that fetch manifests or watch cytrus.json archive
+    // what they see, the others and a dry run leave the disk alone
+    let archives = matches!(
+        args.command,
+        Some(
+            Commands::Download { dry_run: false, .. }
+                | Commands::Update { .. }
+                | Commands::Diff { .. }
+                | Commands::Inspect { .. }
+                | Commands::Verify { .. }
+                | Commands::Repair { .. }
+                | Commands::Watch { .. }
+        )
+    );
+
+    let mut api = Api::with_url(&args.url)
+        .with_timeouts(args.connect_timeout, args.read_timeout)
+        .with_retry(RetryPolicy {
+            retries: args.retries,
+            ..RetryPolicy::default()
+        });
+    if let Some(history) = history.as_ref().filter(|_| archives) {
+        api = api.with_history(history.clone());
+    }
+
+    let Some(command) = args.command else {
+        Cli::command().print_help()?;
+        return Ok(());
+    };
+
+    let result = match command {
+        Commands::Verify {
+            release,
+            source,
+            output,
+            selection,
+            json,
+        } => {
+            let source = match source {
+                Some(source) => source,
+                None => release.latest_version(&api).await?,
+            };
+
+            let manifest_binary = load_manifest(&api, Some(&release), &source).await?;
+            let output = output.unwrap_or_else(|| release.output());
+
+            let report = Report::new(
+                &read_manifest(&manifest_binary)?,
+                &output,
+                &selection.fragments,
+                &selection.filter()?,
+            )?;
+
+            let result = if json {
+                serde_json::to_string_pretty(&report)?
+            } else {
+                report.to_string()
+            };
+
+            if !report.is_valid() {
+                println!("{result}");
+                process::exit(1);
+            }
+
+            result
+        }
+        Commands::Repair {
+            release,
+            source,
+            output,
+            concurrency,
+            selection,
+        } => {
+            let source = match source {
+                Some(source) => source,
+                None => release.latest_version(&api).await?,
+            };
+
+            let manifest_binary = load_manifest(&api, Some(&release), &source).await?;
+            let manifest = read_manifest(&manifest_binary)?;
+            let output = output.unwrap_or_else(|| release.output());
+            let filter = selection.filter()?;
+
+            let downloader = with_progress(
+                with_store(
+                    Downloader::new(api, release.game, concurrency)
+                        .with_fragments(selection.fragments)
+                        .with_filter(filter),
+                    args.store,
+                    args.no_store,
+                ),
+                args.progress,
+            );
+
+            let report = downloader.verify(&manifest, &output)?;
+            println!("{report}");
+
+            if report.is_valid() {
+                return Ok(());
+            }
+
+            let local = LocalState::from_report(&manifest, &output, &report)?;
+            // the journal of a manifest file is kept by its hash, the path
+            // may hold another version the next time
+            let key = if Path::new(&source).is_file() {
+                hash_bytes(&manifest_binary)
+            } else {
+                source
+            };
+            let mut journal = Journal::open(&output, &key)?;
+
+            let summary = downloader
+                .update(&manifest, &output, &local, &mut journal)
+                .await?;
+            journal.finish()?;
+
+            println!(
+                "Repaired {} files from {} bundles",
+                summary.files, summary.bundles
+            );
+
+            String::new()
+        }
+        Commands::History {
             game,
             platform,
-            beta,
+            assets,
+            channel,
+            manifests,
+            snapshots,
+            json,
         } => {
-            let version = Api::get_latest_version(&game, &platform, &beta).await?;
+            let Some(history) = &history else {
+                return Ok(());
+            };
 
-            println!("Latest version: {version}");
+            let query = Query {
+                game,
+                target: match platform {
+                    Some(platform) => Some(Target::Platform(platform)),
+                    None if assets => Some(Target::Assets),
+                    None => None,
+                },
+                channel,
+            };
 
-            let manifest_binary = Api::get_manifiest(&game, &platform, &version, &beta).await?;
+            if snapshots {
+                render_entries(&history.snapshots()?, json)?
+            } else if manifests {
+                render_entries(&history.manifests(&query)?, json)?
+            } else {
+                render_entries(&history.versions(&query)?, json)?
+            }
+        }
+        Commands::Watch {
+            watches,
+            interval,
+            exec,
+            events,
+        } => {
+            let mut watcher = Watcher::new(api, watches);
+            let mut ticks = tokio::time::interval(interval);
+            // a slow poll or hook pushes the next poll back instead of
+            // polling in a burst to catch up
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut started = false;
 
-            let manifest = flatbuffers::root::<Manifest>(&manifest_binary)?;
+            loop {
+                ticks.tick().await;
 
-            let fragments = manifest.fragments();
+                // the cdn failing once doesn't stop the watch
+                let changes = match watcher.poll().await {
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        continue;
+                    }
+                    changes => changes?,
+                };
+
+                if !started {
+                    for (watch, version) in watcher.versions() {
+                        println!("Watching {watch} at {}", version.unwrap_or("none"));
+                    }
+                    started = true;
+                }
+
+                // a failed hook or events file doesn't stop the watch either
+                for event in &changes {
+                    println!("{event}");
+
+                    if let Some(path) = &events {
+                        if let Err(err) = write_event(path, event) {
+                            eprintln!("Error: {err}");
+                        }
+                    }
+                    if let Some(command) = &exec {
+                        if let Err(err) = run_hook(command, event).await {
+                            eprintln!("Error: {err}");
+                        }
+                    }
+                }
+            }
+        }
+        Commands::List { json } => {
+            let games = GameList::new(&api.get_cytrus().await?);
+
+            if json {
+                serde_json::to_string_pretty(&games)?
+            } else {
+                games.to_string()
+            }
+        }
+        Commands::Version { release } => release.latest_version(&api).await?,
+        Commands::Download {
+            release,
+            version,
+            output,
+            concurrency,
+            selection,
+            list_fragments,
+            dry_run,
+        } => {
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    let version = release.latest_version(&api).await?;
+                    println!("Latest version: {version}");
+                    version
+                }
+            };
+
+            let manifest_binary = release.manifest(&api, &version).await?;
+
+            let manifest = read_manifest(&manifest_binary)?;
+
+            for fragment in manifest.fragments().unwrap_or_default() {
+                let files = fragment.files().unwrap_or_default();
 
-            for fragment in fragments.unwrap() {
                 println!(
-                    "{} - {} - {}",
-                    fragment.name().unwrap(),
-                    fragment.bundles().unwrap_or_default().len(),
-                    fragment.files().unwrap().len()
+                    "{} - {} files - {} bytes",
+                    fragment.name().unwrap_or_default(),
+                    files.len(),
+                    files.iter().map(|file| file.size_()).sum::<i64>()
                 );
-                for file in fragment.files().unwrap() {
-                    println!(
-                        "\t{} - {} - {}",
-                        file.name().unwrap(),
-                        file.size_(),
-                        file.chunks().unwrap_or_default().len()
-                    );
+            }
+
+            if list_fragments {
+                return Ok(());
+            }
+
+            let output = output.unwrap_or_else(|| release.output());
+            let mut journal = Journal::open(&output, &version)?;
+
+            let downloader = with_progress(
+                with_store(
+                    Downloader::new(api, release.game, concurrency)
+                        .with_filter(selection.filter()?)
+                        .with_fragments(selection.fragments),
+                    args.store,
+                    args.no_store,
+                ),
+                args.progress,
+            );
+
+            if dry_run {
+                let plan = downloader.plan(&manifest, &output, &LocalState::default(), &journal)?;
+                println!("{plan}");
+                plan.check_space(&output, downloader.store().map(Store::root))?;
+
+                return Ok(());
+            }
+
+            let summary = downloader
+                .download(&manifest, &output, &mut journal)
+                .await?;
+            journal.finish()?;
+
+            println!(
+                "Downloaded {} files from {} bundles into {}",
+                summary.files,
+                summary.bundles,
+                output.display()
+            );
+
+            String::new()
+        }
+        Commands::Cat {
+            release,
+            version,
+            file,
+            output,
+            concurrency,
+        } => {
+            let version = match version {
+                Some(version) => version,
+                None => release.latest_version(&api).await?,
+            };
+
+            let manifest_binary = release.manifest(&api, &version).await?;
+            let manifest = read_manifest(&manifest_binary)?;
+
+            // the store is only read, and only when it already exists
+            let mut downloader = Downloader::new(api, release.game, concurrency);
+            if let Some(root) = args
+                .store
+                .or_else(Store::default_root)
+                .filter(|root| !args.no_store && root.is_dir())
+            {
+                downloader = downloader.with_store(Store::open(&root));
+            }
+            let downloader = with_progress(downloader, args.progress);
+
+            match output {
+                Some(output) => {
+                    let mut out = io::BufWriter::new(fs::File::create(&output)?);
+                    if let Err(err) = downloader.cat(&manifest, &file, &mut out).await {
+                        drop(out);
+                        fs::remove_file(&output)?;
+                        return Err(err.into());
+                    }
+                }
+                None => {
+                    let mut out = io::BufWriter::new(io::stdout().lock());
+                    match downloader.cat(&manifest, &file, &mut out).await {
+                        // the reader of the pipe stopped early, as `head` does
+                        Err(cytrus::Error::Io(err)) if err.kind() == io::ErrorKind::BrokenPipe => {}
+                        result => {
+                            result?;
+                        }
+                    }
                 }
             }
 
+            return Ok(());
+        }
+        Commands::Update {
+            release,
+            version,
+            output,
+            previous,
+            concurrency,
+        } => {
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    let version = release.latest_version(&api).await?;
+                    println!("Latest version: {version}");
+                    version
+                }
+            };
+
+            let manifest_binary = release.manifest(&api, &version).await?;
+
+            let manifest = read_manifest(&manifest_binary)?;
+
+            let output = output.unwrap_or_else(|| release.output());
+            let local = match previous {
+                Some(previous) => {
+                    let previous_binary = fs::read(previous)?;
+                    let previous = read_manifest(&previous_binary)?;
+
+                    LocalState::from_manifest(&previous, &manifest, &output)?
+                }
+                None => LocalState::from_install(&manifest, &output)?,
+            };
+
+            let mut journal = Journal::open(&output, &version)?;
+
+            let downloader = with_progress(
+                with_store(
+                    Downloader::new(api, release.game, concurrency),
+                    args.store,
+                    args.no_store,
+                ),
+                args.progress,
+            );
+            let summary = downloader
+                .update(&manifest, &output, &local, &mut journal)
+                .await?;
+            journal.finish()?;
+
+            println!(
+                "Updated {} files from {} bundles, {} files were up to date",
+                summary.files, summary.bundles, summary.unchanged
+            );
+
             String::new()
         }
+        Commands::Diff {
+            release,
+            from,
+            to,
+            json,
+        } => {
+            let from_binary = load_manifest(&api, release.as_ref(), &from).await?;
+            let to_binary = load_manifest(&api, release.as_ref(), &to).await?;
+
+            let diff =
+                ManifestDiff::new(&read_manifest(&from_binary)?, &read_manifest(&to_binary)?);
+
+            if json {
+                serde_json::to_string_pretty(&diff)?
+            } else {
+                diff.to_string()
+            }
+        }
+        Commands::Inspect {
+            release,
+            source,
+            format,
+            sort,
+            chunks,
+            selection,
+        } => {
+            let source = match source {
+                Some(source) => source,
+                None => {
+                    fetched_release(release.as_ref(), "the latest version")?
+                        .latest_version(&api)
+                        .await?
+                }
+            };
+
+            let manifest_binary = load_manifest(&api, release.as_ref(), &source).await?;
+
+            let info = ManifestInfo::new(
+                &read_manifest(&manifest_binary)?,
+                &selection.fragments,
+                &selection.filter()?,
+                sort,
+            )?;
+
+            info.render(format, chunks)
+        }
     };
 
     println!("{result}");
@@ -0,0 +1,133 @@
+use std::{io::Write, sync::Mutex};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+
+/// Something that happened during a download
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// the download was planned, nothing was fetched yet
+    Planned {
+        /// files that will be written
+        files: usize,
+        /// bundles that will be fetched
+        bundles: usize,
+        /// bytes of the bundles, or of their ranges, that will be fetched
+        transfer: u64,
+    },
+    BundleStarted {
+        bundle: String,
+    },
+    BundleFinished {
+        bundle: String,
+        size: u64,
+    },
+    /// bytes fetched since the download started
+    Transferred {
+        bytes: u64,
+    },
+    /// a rebuilt file was checked against its hash, a file that doesn't
+    /// match is fetched again once before the download fails
+    Verified {
+        file: String,
+        valid: bool,
+        /// the file didn't match and is fetched again
+        retrying: bool,
+    },
+    FileCompleted {
+        file: String,
+        size: u64,
+    },
+    Finished {
+        files: usize,
+        unchanged: usize,
+        bundles: usize,
+    },
+}
+
+/// Receives the events of a download, any `Fn(&Event)` is a reporter
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+impl<F: Fn(&Event) + Send + Sync> Reporter for F {
+    fn report(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Reports nothing
+pub struct Silent;
+
+impl Reporter for Silent {
+    fn report(&self, _event: &Event) {}
+}
+
+/// A progress bar of the fetched bytes on stderr, hidden when stderr isn't
+/// a terminal
+pub struct TerminalReporter {
+    bar: ProgressBar,
+}
+
+impl Default for TerminalReporter {
+    fn default() -> Self {
+        TerminalReporter::new()
+    }
+}
+
+impl TerminalReporter {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {bytes_per_sec} {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+        );
+
+        TerminalReporter { bar }
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::Planned { transfer, .. } => self.bar.set_length(*transfer),
+            Event::Transferred { bytes } => self.bar.set_position(*bytes),
+            Event::Verified {
+                file,
+                retrying: true,
+                ..
+            } => self
+                .bar
+                .println(format!("{file} doesn't match its hash, fetching it again")),
+            Event::FileCompleted { file, .. } => self.bar.set_message(file.clone()),
+            Event::Finished { .. } => self.bar.finish_and_clear(),
+            _ => {}
+        }
+    }
+}
+
+/// Writes every event as a json line
+pub struct JsonReporter<W> {
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonReporter<W> {
+    pub fn new(out: W) -> Self {
+        JsonReporter {
+            out: Mutex::new(out),
+        }
+    }
+}
+
+impl<W: Write + Send> Reporter for JsonReporter<W> {
+    fn report(&self, event: &Event) {
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+
+        if let Ok(mut out) = self.out.lock() {
+            // a closed output doesn't stop the download
+            let _ = writeln!(out, "{line}");
+        }
+    }
+}
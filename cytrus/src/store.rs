@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    download::hash_bytes,
+    error::Result,
+    files::{user_dir, write_atomic},
+};
+
+/// Chunks stored by their hash, shared by every game and version
+///
+/// A chunk lives at `<root>/<first two digits of the hash>/<hash>`, the same
+/// layout the cdn uses for bundles. Chunks are checked against their hash
+/// when read and a corrupted chunk is dropped from the store. The directories
+/// are only created once a chunk is stored.
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn open(root: &Path) -> Self {
+        Store {
+            root: root.to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `$XDG_CACHE_HOME/cytrus/chunks`, or `~/.cache/cytrus/chunks`
+    pub fn default_root() -> Option<PathBuf> {
+        Some(user_dir("XDG_CACHE_HOME", ".cache")?.join("chunks"))
+    }
+
+    /// Chunks live in a directory named after the first two characters of
+    /// their hash
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.root.join(hash.get(0..2).unwrap_or(hash)).join(hash)
+    }
+
+    /// The chunk is stored, its content is only checked when read
+    pub fn contains(&self, hash: &str) -> bool {
+        self.path(hash).is_file()
+    }
+
+    /// Reads a chunk, `None` when it isn't stored or doesn't match its hash
+    pub fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(hash);
+
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        if hash_bytes(&data) != hash {
+            fs::remove_file(path)?;
+            return Ok(None);
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Stores a chunk, a chunk that is already stored is left untouched
+    pub fn insert(&self, hash: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(hash);
+
+        if path.is_file() {
+            return Ok(());
+        }
+
+        write_atomic(&path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_are_stored_by_their_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(&dir.path().join("chunks"));
+        let hash = hash_bytes(b"chunk");
+
+        assert!(!store.contains(&hash));
+        assert_eq!(store.get(&hash).unwrap(), None);
+
+        store.insert(&hash, b"chunk").unwrap();
+
+        assert!(store.contains(&hash));
+        assert_eq!(
+            store.path(&hash),
+            dir.path().join("chunks").join(&hash[0..2]).join(&hash)
+        );
+        assert_eq!(store.get(&hash).unwrap().unwrap(), b"chunk");
+    }
+
+    #[test]
+    fn tampered_chunks_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Store::open(dir.path());
+        let hash = hash_bytes(b"chunk");
+
+        store.insert(&hash, b"chunk").unwrap();
+        fs::write(store.path(&hash), b"tampered").unwrap();
+
+        assert_eq!(store.get(&hash).unwrap(), None);
+        assert!(!store.contains(&hash));
+    }
+}
@@ -0,0 +1,338 @@
+//! Manifests built in memory and a local server standing in for the cdn,
+//! for the tests
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use flatbuffers::FlatBufferBuilder;
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::{download::to_hex, manifiest_generated::*};
+
+/// A file of a test manifest, its content is a single chunk
+pub struct TestFile {
+    pub name: String,
+    pub content: Vec<u8>,
+    /// size written in the manifest, the size of the content by default
+    pub size: i64,
+    /// offset of its chunk written in the manifest, 0 by default
+    pub offset: i64,
+    pub executable: bool,
+    pub symlink: Option<String>,
+    /// the file and its chunk have a hash in the manifest
+    pub hashed: bool,
+}
+
+impl TestFile {
+    pub fn new(name: &str, content: &[u8]) -> Self {
+        TestFile {
+            name: name.to_string(),
+            content: content.to_vec(),
+            size: content.len() as i64,
+            offset: 0,
+            executable: false,
+            symlink: None,
+            hashed: true,
+        }
+    }
+
+    pub fn symlink(name: &str, target: &str) -> Self {
+        TestFile {
+            symlink: Some(target.to_string()),
+            ..TestFile::new(name, b"")
+        }
+    }
+}
+
+fn hash(data: &[u8]) -> Vec<i8> {
+    Sha1::digest(data).iter().map(|byte| *byte as i8).collect()
+}
+
+/// A manifest and the bundles it references
+pub struct TestManifest {
+    pub data: Vec<u8>,
+    /// hash and content of every bundle
+    pub bundles: Vec<(String, Vec<u8>)>,
+}
+
+/// Builds a manifest of `fragments`, each fragment has one bundle holding
+/// the content of its files back to back
+pub fn manifest(fragments: Vec<(&str, Vec<TestFile>)>) -> TestManifest {
+    let mut builder = FlatBufferBuilder::new();
+    let mut bundles = Vec::new();
+    let mut fragment_offsets = Vec::new();
+
+    for (name, files) in fragments {
+        let mut bundle = Vec::new();
+        let mut bundle_chunks = Vec::new();
+        let mut file_offsets = Vec::new();
+
+        for file in &files {
+            let file_hash = file
+                .hashed
+                .then(|| builder.create_vector(&hash(&file.content)));
+
+            let chunks = if file.content.is_empty() {
+                None
+            } else {
+                let chunk_hash = file
+                    .hashed
+                    .then(|| builder.create_vector(&hash(&file.content)));
+                let chunk = Chunk::create(
+                    &mut builder,
+                    &ChunkArgs {
+                        hash: chunk_hash,
+                        size_: file.content.len() as i64,
+                        offset: file.offset,
+                    },
+                );
+
+                let bundle_hash = file
+                    .hashed
+                    .then(|| builder.create_vector(&hash(&file.content)));
+                bundle_chunks.push(Chunk::create(
+                    &mut builder,
+                    &ChunkArgs {
+                        hash: bundle_hash,
+                        size_: file.content.len() as i64,
+                        offset: bundle.len() as i64,
+                    },
+                ));
+                bundle.extend_from_slice(&file.content);
+
+                Some(builder.create_vector(&[chunk]))
+            };
+
+            let name = builder.create_string(&file.name);
+            let symlink = file
+                .symlink
+                .as_deref()
+                .map(|target| builder.create_string(target));
+
+            file_offsets.push(File::create(
+                &mut builder,
+                &FileArgs {
+                    name: Some(name),
+                    size_: file.size,
+                    hash: file_hash,
+                    chunks,
+                    executable: file.executable,
+                    symlink,
+                },
+            ));
+        }
+
+        let mut bundle_offsets = Vec::new();
+        if !bundle.is_empty() {
+            let bundle_hash = hash(&bundle);
+            let hash_offset = builder.create_vector(&bundle_hash);
+            let chunks = builder.create_vector(&bundle_chunks);
+
+            bundle_offsets.push(Bundle::create(
+                &mut builder,
+                &BundleArgs {
+                    hash: Some(hash_offset),
+                    chunks: Some(chunks),
+                },
+            ));
+
+            let bytes: Vec<u8> = bundle_hash.iter().map(|byte| *byte as u8).collect();
+            bundles.push((to_hex(&bytes), bundle));
+        }
+
+        let name = builder.create_string(name);
+        let files = builder.create_vector(&file_offsets);
+        let bundle_offsets = builder.create_vector(&bundle_offsets);
+
+        fragment_offsets.push(Fragment::create(
+            &mut builder,
+            &FragmentArgs {
+                name: Some(name),
+                files: Some(files),
+                bundles: Some(bundle_offsets),
+            },
+        ));
+    }
+
+    let fragments = builder.create_vector(&fragment_offsets);
+    let manifest = Manifest::create(
+        &mut builder,
+        &ManifestArgs {
+            fragments: Some(fragments),
+        },
+    );
+    builder.finish(manifest, None);
+
+    TestManifest {
+        data: builder.finished_data().to_vec(),
+        bundles,
+    }
+}
+
+/// Path of a bundle of dofus on the cdn
+pub fn bundle_path(hash: &str) -> String {
+    format!("/dofus/bundles/{}/{hash}", &hash[0..2])
+}
+
+/// A request received by a [`Server`]
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub path: String,
+    /// the `Range` header
+    pub range: Option<String>,
+}
+
+#[derive(Default)]
+struct Routes {
+    /// bodies of a path answered in turn, the last one answers from then on
+    bodies: HashMap<String, Vec<Vec<u8>>>,
+    /// requests that answer `503 Service Unavailable` before any other
+    failures: usize,
+    /// range requests answer the whole body with `200 OK`
+    ignore_ranges: bool,
+    requests: Vec<Request>,
+}
+
+/// A local http server standing in for the cdn, an unknown path answers
+/// `404 Not Found` and a range request `206 Partial Content`
+#[derive(Clone)]
+pub struct Server {
+    pub url: String,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl Server {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let server = Server { url, routes };
+
+        let shared = server.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+
+                let mut head = Vec::new();
+                let mut buffer = [0; 1024];
+                while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    head.extend_from_slice(&buffer[..read]);
+                }
+
+                let (status, headers, body) = shared.answer(&String::from_utf8_lossy(&head));
+
+                let response = format!(
+                    "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.write_all(&body).await.unwrap();
+            }
+        });
+
+        server
+    }
+
+    /// Status line, extra headers and body of the answer to a request
+    fn answer(&self, head: &str) -> (&'static str, String, Vec<u8>) {
+        let path = head.split(' ').nth(1).unwrap_or_default().to_string();
+        let range = head.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("range")
+                .then(|| value.trim().to_string())
+        });
+
+        let mut routes = self.routes.lock().unwrap();
+        routes.requests.push(Request {
+            path: path.clone(),
+            range: range.clone(),
+        });
+
+        if routes.failures > 0 {
+            routes.failures -= 1;
+            return ("503 Service Unavailable", String::new(), Vec::new());
+        }
+
+        let Some(bodies) = routes.bodies.get_mut(&path) else {
+            return ("404 Not Found", String::new(), Vec::new());
+        };
+        let body = if bodies.len() > 1 {
+            bodies.remove(0)
+        } else {
+            bodies[0].clone()
+        };
+
+        let range = range.filter(|_| !routes.ignore_ranges).and_then(|range| {
+            let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+            Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+        });
+
+        match range {
+            Some((start, end)) => {
+                let end = end.min(body.len().saturating_sub(1));
+                let headers = format!("Content-Range: bytes {start}-{end}/{}\r\n", body.len());
+                let body = body.get(start..=end).unwrap_or_default().to_vec();
+                ("206 Partial Content", headers, body)
+            }
+            None => ("200 OK", String::new(), body),
+        }
+    }
+
+    /// Answers `body` to every request of `path`
+    pub fn route(&self, path: &str, body: &[u8]) {
+        self.route_in_turn(path, vec![body.to_vec()]);
+    }
+
+    /// Answers `bodies` in turn to the requests of `path`, the last one
+    /// answers from then on
+    pub fn route_in_turn(&self, path: &str, bodies: Vec<Vec<u8>>) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.bodies.insert(path.to_string(), bodies);
+    }
+
+    /// Serves the bundles of `manifest` and the manifest itself as the
+    /// linux main manifest of dofus `version`
+    pub fn route_manifest(&self, version: &str, manifest: &TestManifest) {
+        self.route(
+            &format!("/dofus/releases/main/linux/{version}.manifest"),
+            &manifest.data,
+        );
+        for (hash, bundle) in &manifest.bundles {
+            self.route(&bundle_path(hash), bundle);
+        }
+    }
+
+    /// Answers `503 Service Unavailable` to the next `failures` requests
+    pub fn fail(&self, failures: usize) {
+        self.routes.lock().unwrap().failures = failures;
+    }
+
+    /// Answers range requests with the whole body, like a server without
+    /// range support
+    pub fn ignore_ranges(&self) {
+        self.routes.lock().unwrap().ignore_ranges = true;
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.routes.lock().unwrap().requests.clone()
+    }
+
+    /// Requests received so far for `path`
+    pub fn requests_of(&self, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.path == path)
+            .collect()
+    }
+}
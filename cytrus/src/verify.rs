@@ -0,0 +1,384 @@
+use std::{collections::HashSet, fmt, fs, io::ErrorKind, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    download::{file_path, hash_file, to_hex},
+    error::Result,
+    filter::{select_fragments, Filter},
+    manifiest_generated::{File, Manifest},
+    progress::{Event, Reporter, Silent},
+};
+
+/// Why a file of the install doesn't match the manifest
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "lowercase")]
+pub enum Reason {
+    Size {
+        expected: u64,
+        actual: u64,
+    },
+    Hash {
+        expected: String,
+        actual: String,
+    },
+    /// the file lacks the executable bit
+    Executable,
+    Symlink {
+        expected: String,
+        actual: Option<String>,
+    },
+}
+
+impl Reason {
+    /// The content of the file is wrong, it has to be rebuilt from its chunks
+    pub fn is_content(&self) -> bool {
+        matches!(self, Reason::Size { .. } | Reason::Hash { .. })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Corrupted {
+    pub name: String,
+    #[serde(flatten)]
+    pub reason: Reason,
+}
+
+/// Files of an install compared to a manifest
+#[derive(Serialize)]
+pub struct Report {
+    /// files of the manifest that match it
+    pub valid: usize,
+    pub missing: Vec<String>,
+    pub corrupted: Vec<Corrupted>,
+    /// files of the install that are not in the manifest
+    pub extra: Vec<String>,
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
+enum Status {
+    Valid,
+    Missing,
+    Corrupted(Reason),
+}
+
+/// Compares a file of the manifest with the one at `path`
+fn check_file(file: &File, path: &Path) -> Result<Status> {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Status::Missing),
+        Err(err) => return Err(err.into()),
+    };
+
+    if let Some(target) = file.symlink().filter(|target| !target.is_empty()) {
+        let actual = fs::read_link(path).ok();
+
+        if actual.as_deref() == Some(Path::new(target)) {
+            return Ok(Status::Valid);
+        }
+
+        return Ok(Status::Corrupted(Reason::Symlink {
+            expected: target.to_string(),
+            actual: actual.map(|actual| actual.display().to_string()),
+        }));
+    }
+
+    let size = file.size_() as u64;
+    if !metadata.is_file() || metadata.len() != size {
+        return Ok(Status::Corrupted(Reason::Size {
+            expected: size,
+            actual: metadata.len(),
+        }));
+    }
+
+    let expected = to_hex(file.hash().unwrap_or_default().bytes());
+    let actual = hash_file(path)?;
+    if actual != expected {
+        return Ok(Status::Corrupted(Reason::Hash { expected, actual }));
+    }
+
+    if file.executable() && !is_executable(&metadata) {
+        return Ok(Status::Corrupted(Reason::Executable));
+    }
+
+    Ok(Status::Valid)
+}
+
+/// Every file below `directory`, symlinks are listed but not followed
+fn walk(root: &Path, directory: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, files)?;
+        } else {
+            let name: Vec<String> = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+
+            files.push(name.join("/"));
+        }
+    }
+
+    Ok(())
+}
+
+impl Report {
+    /// Hashes the files of `output` that belong to `fragments` and match
+    /// `filter`, every file that isn't in the manifest is extra
+    pub fn new(
+        manifest: &Manifest,
+        output: &Path,
+        fragments: &[String],
+        filter: &Filter,
+    ) -> Result<Self> {
+        Report::check(manifest, output, fragments, filter, &Silent)
+    }
+
+    /// Same as [`Report::new`], every checked file is sent to `reporter` as
+    /// a [`Event::Verified`]
+    pub fn check(
+        manifest: &Manifest,
+        output: &Path,
+        fragments: &[String],
+        filter: &Filter,
+        reporter: &dyn Reporter,
+    ) -> Result<Self> {
+        let mut report = Report {
+            valid: 0,
+            missing: Vec::new(),
+            corrupted: Vec::new(),
+            extra: Vec::new(),
+        };
+        let mut names = HashSet::new();
+
+        for fragment in manifest.fragments().unwrap_or_default() {
+            for file in fragment.files().unwrap_or_default() {
+                names.insert(file.name().unwrap_or_default());
+            }
+        }
+
+        for fragment in select_fragments(manifest, fragments)? {
+            for file in fragment.files().unwrap_or_default() {
+                let name = file.name().unwrap_or_default();
+
+                if !filter.matches(name) {
+                    continue;
+                }
+
+                let status = check_file(&file, &file_path(output, &file)?)?;
+                reporter.report(&Event::Verified {
+                    file: name.to_string(),
+                    valid: matches!(status, Status::Valid),
+                    retrying: false,
+                });
+
+                match status {
+                    Status::Valid => report.valid += 1,
+                    Status::Missing => report.missing.push(name.to_string()),
+                    Status::Corrupted(reason) => report.corrupted.push(Corrupted {
+                        name: name.to_string(),
+                        reason,
+                    }),
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+        if output.is_dir() {
+            walk(output, output, &mut files)?;
+        }
+
+        report.extra = files
+            .into_iter()
+            .filter(|name| !names.contains(name.as_str()))
+            .collect();
+
+        report.missing.sort();
+        report.corrupted.sort_by(|a, b| a.name.cmp(&b.name));
+        report.extra.sort();
+
+        Ok(report)
+    }
+
+    /// The install matches the manifest, extra files are allowed
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Size { expected, actual } => {
+                write!(f, "{actual} bytes, expected {expected}")
+            }
+            Reason::Hash { expected, actual } => write!(f, "hash {actual}, expected {expected}"),
+            Reason::Executable => write!(f, "not executable"),
+            Reason::Symlink { expected, actual } => write!(
+                f,
+                "symlink to {}, expected {expected}",
+                actual.as_deref().unwrap_or("none")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in &self.missing {
+            writeln!(f, "- {name} (missing)")?;
+        }
+        for file in &self.corrupted {
+            writeln!(f, "~ {} ({})", file.name, file.reason)?;
+        }
+        for name in &self.extra {
+            writeln!(f, "+ {name} (extra)")?;
+        }
+
+        write!(
+            f,
+            "{} valid, {} missing, {} corrupted, {} extra files",
+            self.valid,
+            self.missing.len(),
+            self.corrupted.len(),
+            self.extra.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        read_manifest,
+        testing::{manifest, TestFile},
+    };
+
+    /// Reason of a corrupted file of the report
+    fn reason<'a>(report: &'a Report, name: &str) -> &'a Reason {
+        &report
+            .corrupted
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap_or_else(|| panic!("{name} is not corrupted"))
+            .reason
+    }
+
+    #[test]
+    fn files_are_valid_missing_corrupted_or_extra() {
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile::new("valid.txt", b"valid"),
+                TestFile::new("missing.txt", b"missing"),
+                TestFile::new("hash.txt", b"hash"),
+                TestFile::new("dir/size.txt", b"size"),
+            ],
+        )]);
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("valid.txt"), b"valid").unwrap();
+        fs::write(dir.path().join("hash.txt"), b"hasH").unwrap();
+        fs::write(dir.path().join("dir/size.txt"), b"sized").unwrap();
+        fs::write(dir.path().join("dir/extra.txt"), b"extra").unwrap();
+
+        let report = Report::new(&manifest, dir.path(), &[], &Filter::default()).unwrap();
+
+        assert!(!report.is_valid());
+        assert_eq!(report.valid, 1);
+        assert_eq!(report.missing, ["missing.txt"]);
+        assert_eq!(report.extra, ["dir/extra.txt"]);
+        assert_eq!(report.corrupted.len(), 2);
+        assert!(matches!(
+            reason(&report, "dir/size.txt"),
+            Reason::Size {
+                expected: 4,
+                actual: 5
+            }
+        ));
+        assert!(matches!(reason(&report, "hash.txt"), Reason::Hash { .. }));
+    }
+
+    #[test]
+    fn filtered_files_are_not_checked() {
+        let test = manifest(vec![(
+            "main",
+            vec![TestFile::new("a.txt", b"a"), TestFile::new("b.log", b"b")],
+        )]);
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+
+        let filter = Filter::new(&["*.txt".to_string()], &[]).unwrap();
+        let report = Report::new(&manifest, dir.path(), &[], &filter).unwrap();
+
+        assert!(report.is_valid());
+        assert_eq!(report.valid, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable_bits_and_symlinks_are_checked() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let test = manifest(vec![(
+            "main",
+            vec![
+                TestFile {
+                    executable: true,
+                    ..TestFile::new("game", b"game")
+                },
+                TestFile::new("a.txt", b"a"),
+                TestFile::symlink("link", "a.txt"),
+                TestFile::symlink("missing-link", "a.txt"),
+            ],
+        )]);
+        let manifest = read_manifest(&test.data).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("game"), b"game").unwrap();
+        fs::set_permissions(dir.path().join("game"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        symlink("game", dir.path().join("link")).unwrap();
+        fs::write(dir.path().join("missing-link"), b"a").unwrap();
+
+        let report = Report::new(&manifest, dir.path(), &[], &Filter::default()).unwrap();
+
+        assert_eq!(report.valid, 1);
+        assert!(matches!(reason(&report, "game"), Reason::Executable));
+        assert!(matches!(
+            reason(&report, "link"),
+            Reason::Symlink { expected, actual: Some(actual) }
+                if expected == "a.txt" && actual == "game"
+        ));
+        assert!(matches!(
+            reason(&report, "missing-link"),
+            Reason::Symlink { actual: None, .. }
+        ));
+
+        // only a wrong content has to be fetched again
+        assert!(report
+            .corrupted
+            .iter()
+            .all(|file| !file.reason.is_content()));
+    }
+}
@@ -0,0 +1,195 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::Serialize;
+
+use crate::{
+    api::{Api, Validators},
+    error::{Error, Result},
+    game::{Channel, Game, Target},
+};
+
+/// A channel of a game watched for new versions, written
+/// `<game>/<platform or assets>[/<channel>]`, the channel defaults to main
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Watch {
+    pub game: Game,
+    pub target: Target,
+    pub channel: Channel,
+}
+
+impl FromStr for Watch {
+    type Err = Error;
+
+    fn from_str(watch: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidWatch {
+            watch: watch.to_string(),
+        };
+
+        let mut parts = watch.split('/');
+        let (Some(game), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let channel = parts.next().unwrap_or("main");
+
+        if game.is_empty() || channel.is_empty() || parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        let Ok(game) = game.parse();
+        let Ok(channel) = channel.parse();
+
+        Ok(Watch {
+            game,
+            target: target.parse()?,
+            channel,
+        })
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.game, self.target, self.channel)
+    }
+}
+
+/// The version of a watched channel changed, a version is `None` when the
+/// channel isn't in cytrus.json
+#[derive(Serialize)]
+pub struct Event {
+    pub game: Game,
+    pub target: Target,
+    pub channel: Channel,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}: {} -> {}",
+            self.game,
+            self.target,
+            self.channel,
+            self.old.as_deref().unwrap_or("none"),
+            self.new.as_deref().unwrap_or("none")
+        )
+    }
+}
+
+/// Polls cytrus.json and reports the watched channels whose version changed
+///
+/// The first poll only reads the current versions. cytrus.json is fetched
+/// with the `ETag` and `Last-Modified` of the previous poll, so polling costs
+/// a `304 Not Modified` until something is released.
+pub struct Watcher {
+    api: Api,
+    watches: Vec<Watch>,
+    validators: Validators,
+    versions: Option<HashMap<Watch, Option<String>>>,
+}
+
+impl Watcher {
+    pub fn new(api: Api, watches: Vec<Watch>) -> Self {
+        Watcher {
+            api,
+            watches,
+            validators: Validators::default(),
+            versions: None,
+        }
+    }
+
+    pub async fn poll(&mut self) -> Result<Vec<Event>> {
+        let Some(response) = self.api.get_cytrus_if_changed(&mut self.validators).await? else {
+            return Ok(Vec::new());
+        };
+
+        let versions: HashMap<Watch, Option<String>> = self
+            .watches
+            .iter()
+            .map(|watch| {
+                let version = response
+                    .version(&watch.game, watch.target, &watch.channel)
+                    .ok();
+
+                (watch.clone(), version)
+            })
+            .collect();
+
+        let mut events = Vec::new();
+
+        if let Some(previous) = &self.versions {
+            for watch in &self.watches {
+                let old = previous.get(watch).cloned().flatten();
+                let new = versions.get(watch).cloned().flatten();
+
+                if old != new {
+                    events.push(Event {
+                        game: watch.game.clone(),
+                        target: watch.target,
+                        channel: watch.channel.clone(),
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+
+        self.versions = Some(versions);
+
+        Ok(events)
+    }
+
+    /// Current version of every watched channel, empty before the first poll
+    pub fn versions(&self) -> Vec<(&Watch, Option<&str>)> {
+        self.watches
+            .iter()
+            .filter_map(|watch| {
+                let version = self.versions.as_ref()?.get(watch)?;
+                Some((watch, version.as_deref()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Platform;
+
+    #[test]
+    fn the_channel_defaults_to_main() {
+        let watch: Watch = "dofus/linux".parse().unwrap();
+
+        assert_eq!(watch.game, Game::Dofus);
+        assert_eq!(watch.target, Target::Platform(Platform::Linux));
+        assert_eq!(watch.channel, Channel::Main);
+        assert_eq!(watch.to_string(), "dofus/linux/main");
+    }
+
+    #[test]
+    fn assets_and_other_channels_are_accepted() {
+        let watch: Watch = "retro/assets/beta".parse().unwrap();
+        assert_eq!(watch.target, Target::Assets);
+        assert_eq!(watch.channel, Channel::Beta);
+
+        let watch: Watch = "newgame/windows/ptr".parse().unwrap();
+        assert_eq!(watch.game, Game::Other("newgame".to_string()));
+        assert_eq!(watch.channel, Channel::Other("ptr".to_string()));
+        assert_eq!(watch.to_string(), "newgame/windows/ptr");
+    }
+
+    #[test]
+    fn malformed_watches_are_errors() {
+        for watch in [
+            "",
+            "dofus",
+            "/linux",
+            "dofus/linux/",
+            "dofus/linux/main/extra",
+            "dofus/macos",
+        ] {
+            assert!(watch.parse::<Watch>().is_err(), "{watch} was accepted");
+        }
+    }
+}